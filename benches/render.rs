@@ -0,0 +1,76 @@
+use aseprite_loader::{
+    binary::{blend_mode::BlendMode, color_depth::ColorDepth},
+    builder::{Cel, LayerOptions, SpriteBuilder},
+    loader::{AsepriteFile, LayerSelection},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Build a sprite with `layers` layers of the given blend mode and
+/// opacity. Every layer contains a cel with opaque and translucent pixels
+/// which is partially outside the canvas.
+fn build_sprite(size: u16, layers: usize, blend_mode: BlendMode, opacity: u8) -> Vec<u8> {
+    let mut sprite = SpriteBuilder::new(size, size, ColorDepth::Rgba);
+    let frame = sprite.add_frame(100);
+    for index in 0..layers {
+        let options = LayerOptions {
+            blend_mode,
+            opacity,
+            ..LayerOptions::default()
+        };
+        let layer = sprite
+            .add_layer(format!("Layer {}", index), &options)
+            .unwrap();
+        let pixels = (0..usize::from(size) * usize::from(size))
+            .flat_map(|i| {
+                let alpha = if i % 7 == 0 { 128 } else { 255 };
+                [(i % 256) as u8, (i / 256 % 256) as u8, index as u8, alpha]
+            })
+            .collect();
+        let offset = (index * 8) as i16 - 16;
+        let cel = Cel {
+            x: offset,
+            y: offset,
+            ..Cel::new(size, size, pixels)
+        };
+        sprite.add_cel(frame, layer, cel).unwrap();
+    }
+    sprite.build().unwrap()
+}
+
+fn bench_file(c: &mut Criterion, name: &str, data: &[u8]) {
+    let file = AsepriteFile::load(data).unwrap();
+    let (width, height) = file.size();
+    let mut target = vec![0; usize::from(width) * usize::from(height) * 4];
+    let _ = c.bench_function(&format!("{}/render_frame", name), |b| {
+        b.iter(|| {
+            target.fill(0);
+            file.render_frame(0, &mut target, &LayerSelection::All)
+                .unwrap();
+        })
+    });
+    // The renderer caches the decoded images so this mostly measures
+    // the compositor.
+    let mut renderer = file.renderer();
+    let _ = c.bench_function(&format!("{}/renderer", name), |b| {
+        b.iter(|| {
+            target.fill(0);
+            renderer
+                .render_frame(0, &mut target, &LayerSelection::All)
+                .unwrap();
+        })
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let data = std::fs::read("./tests/combine.aseprite").unwrap();
+    bench_file(c, "combine", &data);
+    let data = build_sprite(256, 8, BlendMode::Normal, 255);
+    bench_file(c, "normal_opaque", &data);
+    let data = build_sprite(256, 8, BlendMode::Normal, 128);
+    bench_file(c, "normal_translucent", &data);
+    let data = build_sprite(256, 8, BlendMode::Multiply, 255);
+    bench_file(c, "multiply", &data);
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use aseprite_loader::loader::{AsepriteFile, LayerSelection, LoadOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Lenient mode reaches all code paths of the strict mode and
+    // additionally the recovery from invalid chunks.
+    let options = LoadOptions { strict: false };
+    let Ok(file) = AsepriteFile::load_with_options(data, &options) else {
+        return;
+    };
+    let (width, height) = file.size();
+    let mut target = vec![0; usize::from(width) * usize::from(height) * 4];
+    for frame_index in 0..file.frames().len() {
+        let _ = file.render_frame(frame_index, &mut target, &LayerSelection::All);
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use aseprite_loader::binary::{file::parse_file, lazy_file::parse_lazy_file};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_file(data);
+    if let Ok(file) = parse_lazy_file(data) {
+        let _ = file.layers();
+        let _ = file.tags();
+        let _ = file.palette();
+        for index in 0..file.frames.len() {
+            let _ = file.parse_frame(index);
+        }
+    }
+});
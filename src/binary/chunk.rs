@@ -1,4 +1,4 @@
-use nom::{bytes::complete::take, multi::count, Parser};
+use nom::bytes::complete::take;
 
 use super::{
     chunk_type::{parse_chunk_type, ChunkType},
@@ -16,7 +16,7 @@ use super::{
         tileset::{parse_tileset_chunk, TilesetChunk},
         user_data::{parse_user_data_chunk, UserDataChunk},
     },
-    errors::{ParseError, ParseResult},
+    errors::{ParseError, ParseErrorKind, ParseResult},
     scalars::dword_size,
 };
 
@@ -37,18 +37,111 @@ pub enum Chunk<'a> {
     Slice(SliceChunk<'a>),
     Tileset(TilesetChunk<'a>),
     Unsupported(u16),
+    /// A chunk which was not parsed as its type was not requested (see
+    /// [`parse_chunks_of_type`]) or as it failed to parse in lenient mode.
+    Skipped(ChunkType),
 }
 
-pub fn parse_chunks(input: &[u8], chunk_count: usize) -> ParseResult<'_, Vec<Chunk<'_>>> {
-    count(parse_chunk, chunk_count).parse(input)
+/// Parse `chunk_count` chunks. If `diagnostics` is given chunks which
+/// fail to parse are returned as [`Chunk::Skipped`] and the errors are
+/// added to `diagnostics`.
+pub fn parse_chunks<'a>(
+    input: &'a [u8],
+    chunk_count: usize,
+    mut diagnostics: Option<&mut Vec<ParseError>>,
+) -> ParseResult<'a, Vec<Chunk<'a>>> {
+    let mut chunks = Vec::new();
+    let mut input = input;
+    for index in 0..chunk_count {
+        let result = parse_chunk(input).map_err(|e| {
+            e.map(|mut e| {
+                e.chunk = Some(index);
+                e
+            })
+        });
+        match (result, diagnostics.as_deref_mut()) {
+            (Ok((rest, chunk)), _) => {
+                chunks.push(chunk);
+                input = rest;
+            }
+            (Err(e), None) => return Err(e),
+            (Err(e), Some(diagnostics)) => {
+                diagnostics.push(ParseError::from_nom(e));
+                // The following chunks can only be found if the size
+                // of the skipped chunk is valid.
+                match skip_chunk(input) {
+                    Ok((rest, _)) => {
+                        if let Ok((_, Ok(chunk_type))) = peek_chunk_type(input) {
+                            chunks.push(Chunk::Skipped(chunk_type));
+                        }
+                        input = rest;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+    Ok((input, chunks))
+}
+
+/// Parse `chunk_count` chunks but only parse the contents of chunks of
+/// the given types. All other chunks are returned as [`Chunk::Skipped`]
+/// so user data chunks still follow the chunk they belong to.
+pub fn parse_chunks_of_type<'a>(
+    input: &'a [u8],
+    chunk_count: usize,
+    chunk_types: &[ChunkType],
+) -> ParseResult<'a, Vec<Chunk<'a>>> {
+    let mut chunks = Vec::new();
+    let mut input = input;
+    for index in 0..chunk_count {
+        let result = match peek_chunk_type(input) {
+            Ok((_, Ok(chunk_type))) if !chunk_types.contains(&chunk_type) => {
+                skip_chunk(input).map(|(rest, _)| (rest, Chunk::Skipped(chunk_type)))
+            }
+            _ => parse_chunk(input),
+        };
+        let (rest, chunk) = result.map_err(|e| {
+            e.map(|mut e| {
+                e.chunk = Some(index);
+                e
+            })
+        })?;
+        chunks.push(chunk);
+        input = rest;
+    }
+    Ok((input, chunks))
+}
+
+fn peek_chunk_type(input: &[u8]) -> ParseResult<'_, Result<ChunkType, u16>> {
+    let (input, _) = take(4usize)(input)?;
+    parse_chunk_type(input)
+}
+
+fn skip_chunk(input: &[u8]) -> ParseResult<'_, &[u8]> {
+    let (input, size) = dword_size(input, 6, ParseErrorKind::InvalidChunkSize)?;
+    take(size - 4)(input)
 }
 
 pub fn parse_chunk(input: &[u8]) -> ParseResult<'_, Chunk<'_>> {
-    let (input, size) = dword_size(input, ParseError::InvalidFrameSize)?;
-    // FIXME handle underflows
+    // The chunk size includes the size itself and the chunk type
+    let (input, size) = dword_size(input, 6, ParseErrorKind::InvalidChunkSize)?;
     let (rest, input) = take(size - 4)(input)?;
     let (chunk_data, chunk_type) = parse_chunk_type(input)?;
-    let chunk = match chunk_type {
+    let chunk = parse_chunk_data(chunk_type, chunk_data).map_err(|e| {
+        e.map(|mut e| {
+            e.chunk_type = chunk_type.ok();
+            e.followed_by(rest)
+        })
+    })?;
+    Ok((rest, chunk))
+}
+
+fn parse_chunk_data(
+    chunk_type: Result<ChunkType, u16>,
+    chunk_data: &[u8],
+) -> Result<Chunk<'_>, nom::Err<ParseError>> {
+    Ok(match chunk_type {
         Ok(ChunkType::Palette0004) => Chunk::Palette0004(parse_old_palette_chunk(chunk_data)?.1),
         Ok(ChunkType::Palette0011) => Chunk::Palette0011(parse_old_palette_chunk(chunk_data)?.1),
         Ok(ChunkType::Layer) => Chunk::Layer(parse_layer_chunk(chunk_data)?.1),
@@ -66,6 +159,5 @@ pub fn parse_chunk(input: &[u8]) -> ParseResult<'_, Chunk<'_>> {
         Ok(ChunkType::Slice) => Chunk::Slice(parse_slice_chunk(chunk_data)?.1),
         Ok(ChunkType::Tileset) => Chunk::Tileset(parse_tileset_chunk(chunk_data)?.1),
         Err(chunk_type) => Chunk::Unsupported(chunk_type),
-    };
-    Ok((rest, chunk))
+    })
 }
@@ -3,7 +3,7 @@ use strum::FromRepr;
 
 use super::{errors::ParseResult, scalars::word};
 
-#[derive(FromRepr, Debug, Copy, Clone)]
+#[derive(FromRepr, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ChunkType {
     Palette0004 = 0x0004,
     Palette0011 = 0x0011,
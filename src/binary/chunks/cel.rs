@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use nom::bytes::complete::take;
 use strum::FromRepr;
 
@@ -70,14 +72,62 @@ pub enum CelContent<'a> {
         bitmask_diagonal_flip: Dword,
         /// Row by row, from top to bottom tile by tile
         /// compressed with ZLIB method (see NOTE.3)
-        data: &'a [u8],
+        data: Cow<'a, [u8]>,
     },
     Unknown {
         cel_type: Word,
-        data: &'a [u8],
+        data: Cow<'a, [u8]>,
     },
 }
 
+impl CelChunk<'_> {
+    /// Convert into a cel chunk which does not borrow the input
+    pub fn into_owned(self) -> CelChunk<'static> {
+        CelChunk {
+            layer_index: self.layer_index,
+            x: self.x,
+            y: self.y,
+            opacity: self.opacity,
+            z_index: self.z_index,
+            content: self.content.into_owned(),
+            user_data: self.user_data.map(UserDataChunk::into_owned),
+        }
+    }
+}
+
+impl CelContent<'_> {
+    /// Convert into cel content which does not borrow the input
+    pub fn into_owned(self) -> CelContent<'static> {
+        match self {
+            CelContent::Image(image) => CelContent::Image(image.into_owned()),
+            CelContent::LinkedCel { frame_position } => CelContent::LinkedCel { frame_position },
+            CelContent::CompressedTilemap {
+                width,
+                height,
+                bits_per_tile,
+                bitmask_tile_id,
+                bitmask_x_flip,
+                bitmask_y_flip,
+                bitmask_diagonal_flip,
+                data,
+            } => CelContent::CompressedTilemap {
+                width,
+                height,
+                bits_per_tile,
+                bitmask_tile_id,
+                bitmask_x_flip,
+                bitmask_y_flip,
+                bitmask_diagonal_flip,
+                data: Cow::Owned(data.into_owned()),
+            },
+            CelContent::Unknown { cel_type, data } => CelContent::Unknown {
+                cel_type,
+                data: Cow::Owned(data.into_owned()),
+            },
+        }
+    }
+}
+
 pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
     let (input, layer_index) = word(input)?;
     let (input, x) = short(input)?;
@@ -94,7 +144,7 @@ pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
             CelContent::Image(Image {
                 width,
                 height,
-                data: input,
+                data: Cow::Borrowed(input),
                 compressed: false,
             })
         }
@@ -108,7 +158,7 @@ pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
             CelContent::Image(Image {
                 width,
                 height,
-                data: input,
+                data: Cow::Borrowed(input),
                 compressed: true,
             })
         }
@@ -117,8 +167,8 @@ pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
             let (input, height) = word(input)?;
             let (input, bits_per_tile) = word(input)?;
             let (input, bitmask_tile_id) = dword(input)?;
-            let (input, bitmask_y_flip) = dword(input)?;
             let (input, bitmask_x_flip) = dword(input)?;
+            let (input, bitmask_y_flip) = dword(input)?;
             let (input, bitmask_diagonal_flip) = dword(input)?;
             let (input, _) = take(10usize)(input)?;
             CelContent::CompressedTilemap {
@@ -129,12 +179,12 @@ pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
                 bitmask_x_flip,
                 bitmask_y_flip,
                 bitmask_diagonal_flip,
-                data: input,
+                data: Cow::Borrowed(input),
             }
         }
         CelType::Unknown(cel_type) => CelContent::Unknown {
             cel_type,
-            data: input,
+            data: Cow::Borrowed(input),
         },
     };
     Ok((
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use bitflags::bitflags;
 use nom::{bytes::complete::take, combinator::cond, Parser};
 
@@ -15,12 +17,28 @@ pub struct LayerChunk<'a> {
     pub child_level: Word,
     pub blend_mode: BlendMode,
     pub opacity: Byte,
-    pub name: &'a str,
+    pub name: Cow<'a, str>,
     pub tileset_index: Option<Dword>,
     /// An optional user data chunk following this cel chunk.
     pub user_data: Option<UserDataChunk<'a>>,
 }
 
+impl LayerChunk<'_> {
+    /// Convert into a layer chunk which does not borrow the input
+    pub fn into_owned(self) -> LayerChunk<'static> {
+        LayerChunk {
+            flags: self.flags,
+            layer_type: self.layer_type,
+            child_level: self.child_level,
+            blend_mode: self.blend_mode,
+            opacity: self.opacity,
+            name: Cow::Owned(self.name.into_owned()),
+            tileset_index: self.tileset_index,
+            user_data: self.user_data.map(UserDataChunk::into_owned),
+        }
+    }
+}
+
 bitflags! {
     #[derive(Debug)]
     pub struct LayerFlags: Word {
@@ -76,7 +94,7 @@ pub fn parse_layer_chunk(input: &[u8]) -> ParseResult<'_, LayerChunk<'_>> {
             child_level,
             blend_mode,
             opacity,
-            name,
+            name: Cow::Borrowed(name),
             tileset_index,
             user_data: None,
         },
@@ -4,7 +4,7 @@ use bitflags::bitflags;
 use nom::{bytes::complete::take, combinator::cond, multi::count, Parser};
 
 use crate::binary::{
-    errors::{ParseError, ParseResult},
+    errors::{ParseError, ParseErrorKind, ParseResult},
     palette::PaletteError,
     scalars::{
         parse_color, parse_dword_as_u8, parse_dword_as_usize, parse_string, word, Color, Word,
@@ -13,6 +13,8 @@ use crate::binary::{
 
 #[derive(Debug)]
 pub struct PaletteChunk<'a> {
+    /// Number of colors of the whole palette
+    pub size: usize,
     pub indices: RangeInclusive<u8>,
     pub entries: Vec<PaletteEntry<'a>>,
 }
@@ -30,25 +32,30 @@ bitflags! {
 }
 
 pub fn parse_palette_chunk(input: &[u8]) -> ParseResult<'_, PaletteChunk<'_>> {
-    let (input, palette_size) = parse_dword_as_usize(input)?;
+    // The new palette size is the size of the whole palette. The chunk only
+    // contains the entries from the first to the last changed color index.
+    let (input, size) = parse_dword_as_usize(input)?;
     let (input, first_color_index) = parse_dword_as_u8(
         input,
-        ParseError::PaletteError(PaletteError::FirstColorIndexOutOfBounds),
+        ParseErrorKind::PaletteError(PaletteError::FirstColorIndexOutOfBounds),
     )?;
     let (input, last_color_index) = parse_dword_as_u8(
         input,
-        ParseError::PaletteError(PaletteError::LastColorIndexOutOfBounds),
+        ParseErrorKind::PaletteError(PaletteError::LastColorIndexOutOfBounds),
     )?;
     if first_color_index > last_color_index {
-        return Err(nom::Err::Failure(ParseError::PaletteError(
-            PaletteError::FirstColorIndexGreaterThanLastColorIndex,
-        )));
+        return Err(ParseError::failure(
+            ParseErrorKind::PaletteError(PaletteError::FirstColorIndexGreaterThanLastColorIndex),
+            input,
+        ));
     }
     let (input, _) = take(8usize)(input)?;
-    let (input, entries) = count(parse_palette_entry, palette_size).parse(input)?;
+    let entry_count = usize::from(last_color_index - first_color_index) + 1;
+    let (input, entries) = count(parse_palette_entry, entry_count).parse(input)?;
     Ok((
         input,
         PaletteChunk {
+            size,
             indices: (first_color_index..=last_color_index),
             entries,
         },
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use bitflags::bitflags;
 use nom::{combinator::cond, multi::count, Parser};
 
@@ -9,13 +11,25 @@ use crate::binary::{
 
 #[derive(Debug)]
 pub struct SliceChunk<'a> {
-    pub name: &'a str,
+    pub name: Cow<'a, str>,
     pub flags: SliceFlags,
     pub slice_keys: Vec<SliceKey>,
     /// An optional user data chunk following this cel chunk.
     pub user_data: Option<UserDataChunk<'a>>,
 }
 
+impl SliceChunk<'_> {
+    /// Convert into a slice chunk which does not borrow the input
+    pub fn into_owned(self) -> SliceChunk<'static> {
+        SliceChunk {
+            name: Cow::Owned(self.name.into_owned()),
+            flags: self.flags,
+            slice_keys: self.slice_keys,
+            user_data: self.user_data.map(UserDataChunk::into_owned),
+        }
+    }
+}
+
 bitflags! {
     #[derive(Debug, Copy, Clone)]
     pub struct SliceFlags: Dword {
@@ -60,7 +74,7 @@ pub fn parse_slice_chunk(input: &[u8]) -> ParseResult<'_, SliceChunk<'_>> {
     Ok((
         input,
         SliceChunk {
-            name,
+            name: Cow::Borrowed(name),
             flags,
             slice_keys,
             user_data: None,
@@ -1,11 +1,11 @@
-use std::ops::RangeInclusive;
+use std::{borrow::Cow, ops::RangeInclusive};
 
 use nom::{bytes::complete::take, multi::count, Parser};
 use strum::FromRepr;
 
 use crate::binary::{
     chunks::user_data::UserDataChunk,
-    errors::{ParseError, ParseResult},
+    errors::{ParseError, ParseErrorKind, ParseResult},
     scalars::{byte, parse_string, word, Byte, Word},
 };
 
@@ -22,11 +22,26 @@ pub struct Tag<'a> {
     pub animation_repeat: Word,
     #[deprecated]
     pub color: [u8; 3],
-    pub name: &'a str,
+    pub name: Cow<'a, str>,
     /// An optional user data chunk associated with this tag.
     pub user_data: Option<UserDataChunk<'a>>,
 }
 
+impl Tag<'_> {
+    /// Convert into a tag which does not borrow the input
+    #[allow(deprecated)]
+    pub fn into_owned(self) -> Tag<'static> {
+        Tag {
+            frames: self.frames,
+            animation_direction: self.animation_direction,
+            animation_repeat: self.animation_repeat,
+            color: self.color,
+            name: Cow::Owned(self.name.into_owned()),
+            user_data: self.user_data.map(UserDataChunk::into_owned),
+        }
+    }
+}
+
 #[derive(FromRepr, Debug, Copy, Clone)]
 pub enum AnimationDirection {
     Forward,
@@ -53,9 +68,10 @@ pub fn parse_tag(input: &[u8]) -> ParseResult<'_, Tag<'_>> {
     let (input, from_frame) = word(input)?;
     let (input, to_frame) = word(input)?;
     if from_frame > to_frame {
-        return Err(nom::Err::Failure(ParseError::InvalidFrameRange(
-            from_frame, to_frame,
-        )));
+        return Err(ParseError::failure(
+            ParseErrorKind::InvalidFrameRange(from_frame, to_frame),
+            input,
+        ));
     }
     let (input, animation_direction) = byte(input)?;
     let animation_direction = AnimationDirection::from(animation_direction);
@@ -72,7 +88,7 @@ pub fn parse_tag(input: &[u8]) -> ParseResult<'_, Tag<'_>> {
             animation_direction,
             animation_repeat,
             color: [color[0], color[1], color[2]],
-            name,
+            name: Cow::Borrowed(name),
             user_data: None,
         },
     ))
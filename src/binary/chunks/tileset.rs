@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use bitflags::bitflags;
 use nom::{
     bytes::complete::take,
@@ -30,7 +32,7 @@ pub struct TilesetChunk<'a> {
     /// UI purposes).
     pub base_index: Short,
     /// Name of the tileset
-    pub name: &'a str,
+    pub name: Cow<'a, str>,
     /// Link to external file
     pub external_file: Option<TilesetExternalFile>,
     /// Tiles inside this file
@@ -50,7 +52,26 @@ pub struct TilesetExternalFile {
 pub struct TilesetTiles<'a> {
     /// Compressed Tileset image (see NOTE.3):
     /// (Tile Width) x (Tile Height x Number of Tiles)
-    pub data: &'a [u8],
+    pub data: Cow<'a, [u8]>,
+}
+
+impl TilesetChunk<'_> {
+    /// Convert into a tileset chunk which does not borrow the input
+    pub fn into_owned(self) -> TilesetChunk<'static> {
+        TilesetChunk {
+            id: self.id,
+            flags: self.flags,
+            number_of_tiles: self.number_of_tiles,
+            width: self.width,
+            height: self.height,
+            base_index: self.base_index,
+            name: Cow::Owned(self.name.into_owned()),
+            external_file: self.external_file,
+            tiles: self.tiles.map(|tiles| TilesetTiles {
+                data: Cow::Owned(tiles.data.into_owned()),
+            }),
+        }
+    }
 }
 
 bitflags! {
@@ -101,7 +122,7 @@ pub fn parse_tileset_chunk(input: &[u8]) -> ParseResult<'_, TilesetChunk<'_>> {
             width,
             height,
             base_index,
-            name,
+            name: Cow::Borrowed(name),
             external_file,
             tiles,
         },
@@ -123,5 +144,8 @@ pub fn parse_external_file(input: &[u8]) -> ParseResult<'_, TilesetExternalFile>
 use nom::combinator::map;
 
 pub fn parse_tiles(input: &[u8]) -> ParseResult<'_, TilesetTiles<'_>> {
-    map(flat_map(dword, take), |data| TilesetTiles { data }).parse(input)
+    map(flat_map(dword, take), |data| TilesetTiles {
+        data: Cow::Borrowed(data),
+    })
+    .parse(input)
 }
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use bitflags::bitflags;
 use nom::{
     bytes::complete::take,
@@ -11,7 +13,7 @@ use nom::{
 use strum::FromRepr;
 
 use crate::binary::{
-    errors::{ParseError, ParseResult},
+    errors::{ParseError, ParseErrorKind, ParseResult},
     scalars::{
         byte, dword, fixed, parse_color, parse_dword_as_usize, parse_point, parse_rect, parse_size,
         parse_string, parse_uuid, word, Color, Double, Dword, Fixed, Float, Point, Rect, Size,
@@ -29,7 +31,7 @@ bitflags! {
 
 #[derive(Debug)]
 pub struct UserDataChunk<'a> {
-    pub text: Option<&'a str>,
+    pub text: Option<Cow<'a, str>>,
     pub color: Option<Color>,
     pub properties_maps: Option<ParseResult<'a, Vec<PropertiesMap<'a>>>>,
 }
@@ -42,7 +44,7 @@ pub struct PropertiesMap<'a> {
 
 #[derive(Debug)]
 pub struct Property<'a> {
-    pub name: &'a str,
+    pub name: Cow<'a, str>,
     pub value: Value<'a>,
 }
 
@@ -84,7 +86,7 @@ pub enum Value<'a> {
     Fixed(Fixed),
     Float(f32),
     Double(f64),
-    String(&'a str),
+    String(Cow<'a, str>),
     Point(Point),
     Size(Size),
     Rect(Rect),
@@ -109,7 +111,7 @@ pub enum Vector<'a> {
     Fixed(Vec<Fixed>),
     Float(Vec<Float>),
     Double(Vec<Double>),
-    String(Vec<&'a str>),
+    String(Vec<Cow<'a, str>>),
     Point(Vec<Point>),
     Size(Vec<Size>),
     Rect(Vec<Rect>),
@@ -118,6 +120,105 @@ pub enum Vector<'a> {
     Uuid(Vec<Uuid>),
 }
 
+impl UserDataChunk<'_> {
+    /// Convert into a user data chunk which does not borrow the input.
+    /// The input remaining after the properties maps is not kept.
+    pub fn into_owned(self) -> UserDataChunk<'static> {
+        UserDataChunk {
+            text: self.text.map(|text| Cow::Owned(text.into_owned())),
+            color: self.color,
+            properties_maps: self.properties_maps.map(|result| {
+                result.map(|(_, maps)| {
+                    let rest: &'static [u8] = &[];
+                    (
+                        rest,
+                        maps.into_iter().map(PropertiesMap::into_owned).collect(),
+                    )
+                })
+            }),
+        }
+    }
+}
+
+impl PropertiesMap<'_> {
+    /// Convert into a properties map which does not borrow the input
+    pub fn into_owned(self) -> PropertiesMap<'static> {
+        PropertiesMap {
+            properties: self
+                .properties
+                .into_iter()
+                .map(|property| Property {
+                    name: Cow::Owned(property.name.into_owned()),
+                    value: property.value.into_owned(),
+                })
+                .collect(),
+            extension_entry_id: self.extension_entry_id,
+        }
+    }
+}
+
+impl Value<'_> {
+    /// Convert into a value which does not borrow the input
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::Bool(v) => Value::Bool(v),
+            Value::Int8(v) => Value::Int8(v),
+            Value::Uint8(v) => Value::Uint8(v),
+            Value::Int16(v) => Value::Int16(v),
+            Value::Uint16(v) => Value::Uint16(v),
+            Value::Int32(v) => Value::Int32(v),
+            Value::Uint32(v) => Value::Uint32(v),
+            Value::Int64(v) => Value::Int64(v),
+            Value::Uint64(v) => Value::Uint64(v),
+            Value::Fixed(v) => Value::Fixed(v),
+            Value::Float(v) => Value::Float(v),
+            Value::Double(v) => Value::Double(v),
+            Value::String(v) => Value::String(Cow::Owned(v.into_owned())),
+            Value::Point(v) => Value::Point(v),
+            Value::Size(v) => Value::Size(v),
+            Value::Rect(v) => Value::Rect(v),
+            Value::Vector(v) => Value::Vector(v.into_owned()),
+            Value::MixedVector(v) => {
+                Value::MixedVector(v.into_iter().map(Value::into_owned).collect())
+            }
+            Value::PropertiesMap(v) => Value::PropertiesMap(v.into_owned()),
+            Value::Uuid(v) => Value::Uuid(v),
+        }
+    }
+}
+
+impl Vector<'_> {
+    /// Convert into a vector which does not borrow the input
+    pub fn into_owned(self) -> Vector<'static> {
+        match self {
+            Vector::Mixed(v) => Vector::Mixed(v.into_iter().map(Value::into_owned).collect()),
+            Vector::Bool(v) => Vector::Bool(v),
+            Vector::Int8(v) => Vector::Int8(v),
+            Vector::Uint8(v) => Vector::Uint8(v),
+            Vector::Int16(v) => Vector::Int16(v),
+            Vector::Uint16(v) => Vector::Uint16(v),
+            Vector::Int32(v) => Vector::Int32(v),
+            Vector::Uint32(v) => Vector::Uint32(v),
+            Vector::Int64(v) => Vector::Int64(v),
+            Vector::Uint64(v) => Vector::Uint64(v),
+            Vector::Fixed(v) => Vector::Fixed(v),
+            Vector::Float(v) => Vector::Float(v),
+            Vector::Double(v) => Vector::Double(v),
+            Vector::String(v) => {
+                Vector::String(v.into_iter().map(|s| Cow::Owned(s.into_owned())).collect())
+            }
+            Vector::Point(v) => Vector::Point(v),
+            Vector::Size(v) => Vector::Size(v),
+            Vector::Rect(v) => Vector::Rect(v),
+            Vector::Vector(v) => Vector::Vector(v.into_iter().map(Vector::into_owned).collect()),
+            Vector::PropertiesMap(v) => {
+                Vector::PropertiesMap(v.into_iter().map(PropertiesMap::into_owned).collect())
+            }
+            Vector::Uuid(v) => Vector::Uuid(v),
+        }
+    }
+}
+
 pub fn parse_user_data_chunk(input: &[u8]) -> ParseResult<'_, UserDataChunk<'_>> {
     let (input, flags) = dword(input)?;
     let flags = UserDataFlags::from_bits_truncate(flags);
@@ -132,7 +233,7 @@ pub fn parse_user_data_chunk(input: &[u8]) -> ParseResult<'_, UserDataChunk<'_>>
     Ok((
         input,
         (UserDataChunk {
-            text,
+            text: text.map(Cow::Borrowed),
             color,
             properties_maps,
         }),
@@ -142,10 +243,14 @@ pub fn parse_user_data_chunk(input: &[u8]) -> ParseResult<'_, UserDataChunk<'_>>
 pub fn parse_properties_maps(
     input: &[u8],
 ) -> ParseResult<'_, ParseResult<'_, Vec<PropertiesMap<'_>>>> {
-    let (input, size_maps) = parse_dword_as_usize(input)?;
-    let (input, num_maps) = parse_dword_as_usize(input)?;
-    // FIXME handle underflows
-    let (input, input_maps) = take(size_maps - 8)(input)?;
+    let (rest, size_maps) = parse_dword_as_usize(input)?;
+    let (rest, num_maps) = parse_dword_as_usize(rest)?;
+    // The size includes the size itself and the number of maps
+    let size_maps = size_maps.checked_sub(8).ok_or_else(|| {
+        ParseError::failure(ParseErrorKind::InvalidPropertiesSize(size_maps), input)
+    })?;
+    let input = rest;
+    let (input, input_maps) = take(size_maps)(input)?;
     Ok((
         input,
         count(parse_properties_map, num_maps).parse(input_maps),
@@ -168,14 +273,21 @@ pub fn parse_properties_map(input: &[u8]) -> ParseResult<'_, PropertiesMap<'_>>
 pub fn parse_property(input: &[u8]) -> ParseResult<'_, Property<'_>> {
     let (input, name) = parse_string(input)?;
     let (input, value) = parse_value(input)?;
-    Ok((input, Property { name, value }))
+    Ok((
+        input,
+        Property {
+            name: Cow::Borrowed(name),
+            value,
+        },
+    ))
 }
 
 pub fn parse_value(input: &[u8]) -> ParseResult<'_, Value<'_>> {
-    let (input, prop_type) = word(input)?;
-    let prop_type = PropertyType::from_repr(prop_type).ok_or(nom::Err::Failure(
-        ParseError::InvalidPropertyType(prop_type),
-    ))?;
+    let (rest, prop_type) = word(input)?;
+    let prop_type = PropertyType::from_repr(prop_type).ok_or_else(|| {
+        ParseError::failure(ParseErrorKind::InvalidPropertyType(prop_type), input)
+    })?;
+    let input = rest;
     Ok(match prop_type {
         PropertyType::Bool => map(byte, |b| Value::Bool(b != 0)).parse(input)?,
         PropertyType::Int8 => map(le_i8, Value::Int8).parse(input)?,
@@ -189,7 +301,9 @@ pub fn parse_value(input: &[u8]) -> ParseResult<'_, Value<'_>> {
         PropertyType::Fixed => map(fixed, Value::Fixed).parse(input)?,
         PropertyType::Float => map(le_f32, Value::Float).parse(input)?,
         PropertyType::Double => map(le_f64, Value::Double).parse(input)?,
-        PropertyType::String => map(parse_string, Value::String).parse(input)?,
+        PropertyType::String => {
+            map(parse_string, |s| Value::String(Cow::Borrowed(s))).parse(input)?
+        }
         PropertyType::Point => map(parse_point, Value::Point).parse(input)?,
         PropertyType::Size => map(parse_size, Value::Size).parse(input)?,
         PropertyType::Rect => map(parse_rect, Value::Rect).parse(input)?,
@@ -207,9 +321,9 @@ pub fn parse_vector(input: &[u8]) -> ParseResult<'_, Vector<'_>> {
     if prop_type == 0 {
         return map(count(parse_value, len_elements), Vector::Mixed).parse(input);
     }
-    let prop_type = PropertyType::from_repr(prop_type).ok_or(nom::Err::Failure(
-        ParseError::InvalidPropertyType(prop_type),
-    ))?;
+    let prop_type = PropertyType::from_repr(prop_type).ok_or_else(|| {
+        ParseError::failure(ParseErrorKind::InvalidPropertyType(prop_type), input)
+    })?;
     let (input, vec) = match prop_type {
         PropertyType::Bool => {
             map(count(map(byte, |b| b != 0), len_elements), Vector::Bool).parse(input)?
@@ -225,9 +339,11 @@ pub fn parse_vector(input: &[u8]) -> ParseResult<'_, Vector<'_>> {
         PropertyType::Fixed => map(count(fixed, len_elements), Vector::Fixed).parse(input)?,
         PropertyType::Float => map(count(le_f32, len_elements), Vector::Float).parse(input)?,
         PropertyType::Double => map(count(le_f64, len_elements), Vector::Double).parse(input)?,
-        PropertyType::String => {
-            map(count(parse_string, len_elements), Vector::String).parse(input)?
-        }
+        PropertyType::String => map(
+            count(map(parse_string, Cow::Borrowed), len_elements),
+            Vector::String,
+        )
+        .parse(input)?,
         PropertyType::Point => map(count(parse_point, len_elements), Vector::Point).parse(input)?,
         PropertyType::Size => map(count(parse_size, len_elements), Vector::Size).parse(input)?,
         PropertyType::Rect => map(count(parse_rect, len_elements), Vector::Rect).parse(input)?,
@@ -1,56 +1,193 @@
-use std::str::Utf8Error;
+use std::{fmt, str::Utf8Error};
 
 use nom::IResult;
 
 use super::{
+    chunk_type::ChunkType,
     palette::PaletteError,
     scalars::{Dword, Word},
 };
 
-#[derive(Debug, strum::Display)]
-pub enum ParseError<'a> {
+/// An error which occurred while parsing a file
+///
+/// Besides the [`ParseErrorKind`] this contains the location in the
+/// file at which the error occurred.
+#[derive(Debug, Copy, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Byte offset in the file. This is `None` for errors which are not
+    /// related to a specific position in the file (e.g. an invalid palette).
+    pub offset: Option<usize>,
+    /// Index of the frame containing the error
+    pub frame: Option<usize>,
+    /// Index of the chunk within the frame containing the error
+    pub chunk: Option<usize>,
+    /// Type of the chunk containing the error
+    pub chunk_type: Option<ChunkType>,
+    /// Length of the remaining input at the position of the error. This
+    /// is converted into the `offset` once the error reaches the top level.
+    remaining: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+pub enum ParseErrorKind {
     /// This variant is used when the conversion between
     /// DWORD (u32) to usize fails. The only way this can
     /// happen is when running this code on a 16-bit system.
+    #[error("DWORD does not fit into usize: {0}")]
     DwordToUsize(Dword),
-    /// This variant is used when the frame size is <4
+    /// This variant is used when the frame size is <16
+    #[error("invalid frame size: {0}")]
     InvalidFrameSize(Dword),
-    /// This variant is used when the chunk size is <4
+    /// This variant is used when the chunk size is <6
+    #[error("invalid chunk size: {0}")]
     InvalidChunkSize(Dword),
+    /// This variant is used when the size of the properties
+    /// maps in the user data is <8
+    #[error("invalid properties size: {0}")]
+    InvalidPropertiesSize(usize),
     /// This variant is used when a string does not contain
     /// valid UTF-8 data and `str::from_utf8` returned an error.
-    Utf8Error(Utf8Error),
+    #[error("invalid UTF-8 string")]
+    Utf8Error(#[source] Utf8Error),
     /// The uses index colors but the palette could not be
     /// generated due to errors in the palette chunks.
-    PaletteError(PaletteError),
+    #[error("invalid palette")]
+    PaletteError(#[source] PaletteError),
     /// The range of frame indices was invalid (from > to)
+    #[error("invalid frame range: {0}..={1}")]
     InvalidFrameRange(Word, Word),
     /// This variant is used when a layer index is out
     /// of bounds (layer_index >= layer_count)
+    #[error("layer index out of bounds")]
     LayerIndexOutOfBounds,
+    /// This variant is used when a frame which does not
+    /// exist is requested from a lazily parsed file.
+    #[error("frame index out of bounds: {0}")]
+    FrameIndexOutOfBounds(usize),
     /// This variant is used when a unsupported property
     /// type is found in the user data.
+    #[error("invalid property type: {0}")]
     InvalidPropertyType(Word),
+    /// This variant is used when the input ended before
+    /// parsing was complete.
+    #[error("unexpected end of input")]
+    Incomplete,
     /// This variant is used when the nom combinators return
     /// an error.
-    Nom(nom::error::Error<&'a [u8]>),
+    #[error("{0:?} failed")]
+    Nom(nom::error::ErrorKind),
 }
 
-impl std::error::Error for ParseError<'_> {}
+impl ParseError {
+    /// Create an error at the position of `input`
+    pub fn new(kind: ParseErrorKind, input: &[u8]) -> Self {
+        Self {
+            kind,
+            offset: None,
+            frame: None,
+            chunk: None,
+            chunk_type: None,
+            remaining: Some(input.len()),
+        }
+    }
+    /// Create an error which is not related to a position in the file
+    pub fn without_offset(kind: ParseErrorKind) -> Self {
+        Self {
+            kind,
+            offset: None,
+            frame: None,
+            chunk: None,
+            chunk_type: None,
+            remaining: None,
+        }
+    }
+    /// Create a `nom::Err::Failure` at the position of `input`
+    pub fn failure(kind: ParseErrorKind, input: &[u8]) -> nom::Err<Self> {
+        nom::Err::Failure(Self::new(kind, input))
+    }
+    /// This function must be called when an error leaves a parser which
+    /// operates on a part of the input. `rest` is the input following the
+    /// part.
+    pub(crate) fn followed_by(mut self, rest: &[u8]) -> Self {
+        self.remaining = self.remaining.map(|remaining| remaining + rest.len());
+        self
+    }
+    /// Convert the remaining input length into the `offset` of the error.
+    /// `input` is the complete input of the parser.
+    pub(crate) fn locate(mut self, input: &[u8]) -> Self {
+        if let Some(remaining) = self.remaining {
+            self.offset = Some(input.len().saturating_sub(remaining));
+        }
+        self
+    }
+    /// Convert a `nom::Err` into the contained error
+    pub(crate) fn from_nom(err: nom::Err<Self>) -> Self {
+        match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+            nom::Err::Incomplete(_) => Self::without_offset(ParseErrorKind::Incomplete),
+        }
+    }
+}
 
-impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if let Some(frame) = self.frame {
+            write!(f, " in frame {}", frame)?;
+        }
+        if let Some(chunk) = self.chunk {
+            write!(f, " in chunk {}", chunk)?;
+        }
+        if let Some(chunk_type) = self.chunk_type {
+            write!(f, " ({:?})", chunk_type)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.kind)
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError {
     fn from_error_kind(input: &'a [u8], kind: nom::error::ErrorKind) -> Self {
-        Self::Nom(nom::error::Error::from_error_kind(input, kind))
+        Self::new(ParseErrorKind::Nom(kind), input)
     }
     fn append(_input: &[u8], _kind: nom::error::ErrorKind, other: Self) -> Self {
         other
     }
 }
 
-impl<'a> nom::error::FromExternalError<&'a [u8], Utf8Error> for ParseError<'a> {
-    fn from_external_error(_input: &'a [u8], _kind: nom::error::ErrorKind, e: Utf8Error) -> Self {
-        ParseError::Utf8Error(e)
+impl<'a> nom::error::FromExternalError<&'a [u8], Utf8Error> for ParseError {
+    fn from_external_error(input: &'a [u8], _kind: nom::error::ErrorKind, e: Utf8Error) -> Self {
+        Self::new(ParseErrorKind::Utf8Error(e), input)
     }
 }
 
-pub type ParseResult<'a, O> = IResult<&'a [u8], O, ParseError<'a>>;
+pub type ParseResult<'a, O> = IResult<&'a [u8], O, ParseError>;
+
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+pub enum WriteError {
+    /// The file does not contain any frames
+    #[error("file contains no frames")]
+    NoFrames,
+    /// The number of frames does not fit into a WORD
+    #[error("too many frames: {0}")]
+    TooManyFrames(usize),
+    /// A string is longer than the WORD length prefix allows
+    #[error("string too long: {0} bytes")]
+    StringTooLong(usize),
+    /// A size or count does not fit into a DWORD
+    #[error("size does not fit into a DWORD: {0}")]
+    SizeOverflow(usize),
+    /// The user data contains properties which could not be parsed
+    /// and therefore can't be written.
+    #[error("user data contains invalid properties")]
+    InvalidProperties,
+}
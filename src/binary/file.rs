@@ -1,46 +1,80 @@
-use std::iter::Peekable;
-
-use itertools::Itertools;
+use std::{borrow::Cow, iter::Peekable};
 
 use crate::binary::chunks::user_data::UserDataChunk;
 
 use super::{
+    blend_mode::BlendMode,
     chunk::Chunk,
-    chunks::{cel::CelChunk, layer::LayerChunk, slice::SliceChunk, tags::Tag},
+    chunk_type::ChunkType,
+    chunks::{
+        cel::CelChunk,
+        layer::{LayerChunk, LayerFlags, LayerType},
+        slice::SliceChunk,
+        tags::Tag,
+        tileset::TilesetChunk,
+    },
     color_depth::ColorDepth,
-    errors::ParseError,
+    errors::{ParseError, ParseErrorKind},
     frame::Frame,
     header::Header,
-    palette::{create_palette, Palette},
-    raw_file::parse_raw_file,
+    palette::{create_palettes, Palette},
+    raw_file::{parse_raw_file_with_options, ParseOptions},
     scalars::Word,
 };
 
 #[derive(Debug)]
 pub struct File<'a> {
     pub header: Header,
+    /// Palette of the last frame of indexed files
     pub palette: Option<Palette>,
+    /// Palettes of indexed files together with the index of the first
+    /// frame using them, ordered by the frame index.
+    pub palettes: Vec<(usize, Palette)>,
     pub layers: Vec<LayerChunk<'a>>,
     pub frames: Vec<Frame<'a>>,
     pub tags: Vec<Tag<'a>>,
     pub slices: Vec<SliceChunk<'a>>,
+    pub tilesets: Vec<TilesetChunk<'a>>,
     /// Optional user data associated with the sprite
     pub user_data: Option<UserDataChunk<'a>>,
+    /// Errors of the data which was skipped in lenient mode
+    pub diagnostics: Vec<ParseError>,
+}
+
+pub fn parse_file(input: &[u8]) -> Result<File<'_>, ParseError> {
+    parse_file_with_options(input, &ParseOptions::default())
 }
 
-pub fn parse_file(input: &[u8]) -> Result<File<'_>, nom::Err<ParseError<'_>>> {
-    let raw_file = parse_raw_file(input)?;
-    let palette = match raw_file.header.color_depth {
-        ColorDepth::Indexed => Some(
-            create_palette(&raw_file.header, &raw_file.frames)
-                .map_err(|e| nom::Err::Failure(ParseError::PaletteError(e)))?,
-        ),
-        _ => None,
+pub fn parse_file_with_options<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> Result<File<'a>, ParseError> {
+    let raw_file = parse_raw_file_with_options(input, options)?;
+    let mut diagnostics = raw_file.diagnostics;
+    let mut report = |e: ParseError| {
+        if options.strict {
+            Err(e)
+        } else {
+            diagnostics.push(e);
+            Ok(())
+        }
+    };
+    let palettes = match raw_file.header.color_depth {
+        ColorDepth::Indexed => match create_palettes(&raw_file.frames) {
+            Ok(palettes) => palettes,
+            Err(e) => {
+                report(ParseError::without_offset(ParseErrorKind::PaletteError(e)))?;
+                Vec::new()
+            }
+        },
+        _ => Vec::new(),
     };
+    let palette = palettes.last().map(|(_, palette)| palette.clone());
     let mut frames = Vec::<(Word, Vec<CelChunk<'_>>)>::new();
     let mut layers = Vec::<LayerChunk<'_>>::new();
     let mut tags = Vec::<Tag<'_>>::new();
     let mut slices = Vec::<SliceChunk<'_>>::new();
+    let mut tilesets = Vec::<TilesetChunk<'_>>::new();
     let mut user_data = None;
     for raw_frame in raw_file.frames {
         let mut cels = Vec::<CelChunk<'_>>::new();
@@ -68,15 +102,7 @@ pub fn parse_file(input: &[u8]) -> Result<File<'_>, nom::Err<ParseError<'_>>> {
                 Chunk::Mask(_) => {}
                 Chunk::Path => {}
                 Chunk::Tags(mut tags_chunk) => {
-                    // After a Tags chunk, there will be several user data chunks, one for each
-                    // tag, you should associate the user data in the same order as the tags
-                    // are in the Tags chunk
-                    for tag in &mut tags_chunk.tags {
-                        tag.user_data = next_user_data(&mut chunks);
-                        if tag.user_data.is_none() {
-                            break;
-                        }
-                    }
+                    tags_user_data(&mut tags_chunk.tags, &mut chunks);
                     tags.extend(tags_chunk.tags)
                 }
                 Chunk::UserData(_) => {}
@@ -84,50 +110,69 @@ pub fn parse_file(input: &[u8]) -> Result<File<'_>, nom::Err<ParseError<'_>>> {
                     user_data: next_user_data(&mut chunks),
                     ..slice
                 }),
-                Chunk::Tileset(_) => {}
+                Chunk::Tileset(tileset) => tilesets.push(tileset),
                 Chunk::Unsupported(_) => {}
+                // Keep a hidden placeholder for layers which failed to
+                // parse so the cels still refer to the right layer index.
+                Chunk::Skipped(ChunkType::Layer) => {
+                    let child_level = layers.last().map_or(0, |layer| match layer.layer_type {
+                        LayerType::Group => layer.child_level + 1,
+                        _ => layer.child_level,
+                    });
+                    layers.push(LayerChunk {
+                        flags: LayerFlags::empty(),
+                        layer_type: LayerType::Normal,
+                        child_level,
+                        blend_mode: BlendMode::Normal,
+                        opacity: 0,
+                        name: Cow::Borrowed(""),
+                        tileset_index: None,
+                        user_data: next_user_data(&mut chunks),
+                    })
+                }
+                Chunk::Skipped(_) => {}
             }
         }
         frames.push((raw_frame.duration, cels));
     }
-    let frames = frames
-        .into_iter()
-        .map(|(duration, frame_cels)| {
-            Ok(Frame {
-                duration,
-                cels: {
-                    // Insert cels in the cels vector so that a direct lookup
-                    // by layer index is possible.
-                    let mut cels: Vec<Option<CelChunk<'_>>> = Vec::with_capacity(layers.len());
-                    for _ in 0..layers.len() {
-                        cels.push(None);
-                    }
-                    for cel in frame_cels {
-                        let layer_index: usize = cel.layer_index.into();
-                        if layer_index > layers.len() {
-                            return Err(nom::Err::Failure(ParseError::LayerIndexOutOfBounds));
-                        }
-                        cels[layer_index] = Some(cel);
-                    }
-                    cels
-                },
-            })
-        })
-        .try_collect()?;
+    let frame_cels = frames;
+    let mut frames = Vec::with_capacity(frame_cels.len());
+    for (frame_index, (duration, frame_cels)) in frame_cels.into_iter().enumerate() {
+        // Insert cels in the cels vector so that a direct lookup
+        // by layer index is possible.
+        let mut cels: Vec<Option<CelChunk<'_>>> = Vec::with_capacity(layers.len());
+        for _ in 0..layers.len() {
+            cels.push(None);
+        }
+        for cel in frame_cels {
+            let layer_index: usize = cel.layer_index.into();
+            if layer_index >= layers.len() {
+                let mut e = ParseError::without_offset(ParseErrorKind::LayerIndexOutOfBounds);
+                e.frame = Some(frame_index);
+                report(e)?;
+                continue;
+            }
+            cels[layer_index] = Some(cel);
+        }
+        frames.push(Frame { duration, cels });
+    }
     Ok(File {
         header: raw_file.header,
         palette,
+        palettes,
         layers,
         frames,
         tags,
         slices,
+        tilesets,
         user_data,
+        diagnostics,
     })
 }
 
 /// Peeks ahead to see if the next chunk is a [`UserDataChunk`]. If it is, then the iterator is
 /// advanced, and the owned user data is returned.
-fn next_user_data<'a>(
+pub(super) fn next_user_data<'a>(
     iter: &mut Peekable<impl Iterator<Item = Chunk<'a>>>,
 ) -> Option<UserDataChunk<'a>> {
     let chunk = iter.next_if(|c| matches!(c, Chunk::UserData(_)))?;
@@ -137,6 +182,51 @@ fn next_user_data<'a>(
     Some(user_data)
 }
 
+/// After a Tags chunk, there will be several user data chunks, one for each
+/// tag, you should associate the user data in the same order as the tags
+/// are in the Tags chunk
+pub(super) fn tags_user_data<'a>(
+    tags: &mut [Tag<'a>],
+    iter: &mut Peekable<impl Iterator<Item = Chunk<'a>>>,
+) {
+    for tag in tags {
+        tag.user_data = next_user_data(iter);
+        if tag.user_data.is_none() {
+            break;
+        }
+    }
+}
+
+impl File<'_> {
+    /// Convert into a file which does not borrow the input
+    pub fn into_owned(self) -> File<'static> {
+        File {
+            header: self.header,
+            palette: self.palette,
+            palettes: self.palettes,
+            layers: self
+                .layers
+                .into_iter()
+                .map(LayerChunk::into_owned)
+                .collect(),
+            frames: self.frames.into_iter().map(Frame::into_owned).collect(),
+            tags: self.tags.into_iter().map(Tag::into_owned).collect(),
+            slices: self
+                .slices
+                .into_iter()
+                .map(SliceChunk::into_owned)
+                .collect(),
+            tilesets: self
+                .tilesets
+                .into_iter()
+                .map(TilesetChunk::into_owned)
+                .collect(),
+            user_data: self.user_data.map(UserDataChunk::into_owned),
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
 #[test]
 fn test_parse_file() {
     let input = std::fs::read("./tests/default.aseprite").unwrap();
@@ -185,13 +275,28 @@ fn test_palette() {
 fn test_user_data() {
     let input = std::fs::read("./tests/user_data.aseprite").unwrap();
     let file = parse_file(&input).unwrap();
-    assert_eq!(file.user_data.unwrap().text.unwrap(), "sprite_data");
     assert_eq!(
-        file.layers[0].user_data.as_ref().unwrap().text.unwrap(),
+        file.user_data.unwrap().text.as_deref().unwrap(),
+        "sprite_data"
+    );
+    assert_eq!(
+        file.layers[0]
+            .user_data
+            .as_ref()
+            .unwrap()
+            .text
+            .as_deref()
+            .unwrap(),
         "layer_data"
     );
     assert_eq!(
-        file.slices[0].user_data.as_ref().unwrap().text.unwrap(),
+        file.slices[0]
+            .user_data
+            .as_ref()
+            .unwrap()
+            .text
+            .as_deref()
+            .unwrap(),
         "slice_data"
     );
     assert_eq!(
@@ -202,15 +307,78 @@ fn test_user_data() {
             .as_ref()
             .unwrap()
             .text
+            .as_deref()
             .unwrap(),
         "cel_data"
     );
     for tag in file.tags {
         let user_text = tag.user_data.unwrap().text.unwrap();
-        match tag.name {
+        match &*tag.name {
             "Tag 1" => assert_eq!(user_text, "tag_data_1"),
             "Tag 2" => assert_eq!(user_text, "tag_data_2"),
             _ => {}
         };
     }
 }
+
+#[test]
+fn test_error_location() {
+    use crate::binary::{chunk_type::ChunkType, errors::ParseErrorKind};
+    let input = std::fs::read("./tests/layers.aseprite").unwrap();
+
+    // The first chunk starts after the header (128 bytes) and
+    // the frame header (16 bytes).
+    let mut data = input.clone();
+    data[144..148].copy_from_slice(&3u32.to_le_bytes());
+    let err = parse_file(&data).unwrap_err();
+    assert!(matches!(err.kind, ParseErrorKind::InvalidChunkSize(3)));
+    assert_eq!(err.offset, Some(144));
+    assert_eq!(err.frame, Some(0));
+    assert_eq!(err.chunk, Some(0));
+    assert_eq!(err.chunk_type, None);
+
+    let mut data = input.clone();
+    let name = data.windows(7).position(|w| w == b"Layer 2").unwrap();
+    data[name] = 0xFF;
+    let err = parse_file(&data).unwrap_err();
+    assert!(matches!(err.kind, ParseErrorKind::Utf8Error(_)));
+    // The string starts with its length
+    assert_eq!(err.offset, Some(name - 2));
+    assert_eq!(err.frame, Some(0));
+    assert_eq!(err.chunk_type, Some(ChunkType::Layer));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "invalid UTF-8 string at offset {} in frame 0 in chunk {} (Layer)",
+            name - 2,
+            err.chunk.unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_lenient() {
+    use crate::binary::errors::ParseErrorKind;
+    let mut data = std::fs::read("./tests/layers.aseprite").unwrap();
+    let name = data.windows(7).position(|w| w == b"Layer 2").unwrap();
+    data[name] = 0xFF;
+    assert!(parse_file(&data).is_err());
+    let options = ParseOptions { strict: false };
+    let file = parse_file_with_options(&data, &options).unwrap();
+    assert_eq!(file.diagnostics.len(), 1);
+    assert!(matches!(
+        file.diagnostics[0].kind,
+        ParseErrorKind::Utf8Error(_)
+    ));
+    assert_eq!(file.diagnostics[0].offset, Some(name - 2));
+    assert_eq!(file.diagnostics[0].frame, Some(0));
+    // The skipped layer is replaced by a hidden placeholder so the
+    // following layers keep their index.
+    assert_eq!(file.layers.len(), 3);
+    assert_eq!(file.layers[1].name, "");
+    assert!(!file.layers[1].flags.contains(LayerFlags::VISIBLE));
+    assert_eq!(file.layers[2].name, "Layer 3");
+    let cels = &file.frames[0].cels;
+    assert_eq!(cels.len(), 3);
+    assert_eq!(cels[2].as_ref().unwrap().layer_index, 2);
+}
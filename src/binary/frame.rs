@@ -6,3 +6,17 @@ pub struct Frame<'a> {
     // The cel chunks can directly be indexed via the layer index
     pub cels: Vec<Option<CelChunk<'a>>>,
 }
+
+impl Frame<'_> {
+    /// Convert into a frame which does not borrow the input
+    pub fn into_owned(self) -> Frame<'static> {
+        Frame {
+            duration: self.duration,
+            cels: self
+                .cels
+                .into_iter()
+                .map(|cel| cel.map(CelChunk::into_owned))
+                .collect(),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 use nom::{
     bytes::complete::{tag, take},
+    combinator::eof,
     Parser,
 };
 
 use super::{
     color_depth::{parse_color_depth, ColorDepth},
-    errors::ParseResult,
+    errors::{ParseError, ParseResult},
     scalars::{byte, dword, short, word, Byte, Dword, Short, Word},
 };
 
@@ -26,6 +27,8 @@ pub struct Header {
     pub color_depth: ColorDepth,
     /// Flags:
     ///   1 = Layer opacity has valid value
+    ///   2 = Layer blend mode/opacity is valid for groups
+    ///   4 = Layers have an UUID
     pub flags: Dword,
     /// Speed (milliseconds between frame, like in FLC files)
     /// DEPRECATED: You should use the frame duration field
@@ -55,6 +58,12 @@ pub struct Header {
 
 pub fn parse_header(input: &[u8]) -> ParseResult<'_, Header> {
     let (rest, input) = take(128usize)(input)?;
+    parse_header_data(input)
+        .map(|header| (rest, header))
+        .map_err(|e| e.map(|e| e.followed_by(rest)))
+}
+
+fn parse_header_data(input: &[u8]) -> Result<Header, nom::Err<ParseError>> {
     let (input, file_size) = dword(input)?;
     let (input, _) = tag(HEADER_MAGIC_NUMBER.as_slice()).parse(input)?;
     let (input, frames) = word(input)?;
@@ -76,28 +85,25 @@ pub fn parse_header(input: &[u8]) -> ParseResult<'_, Header> {
     let (input, grid_height) = word(input)?;
     let (input, _) = take(84usize)(input)?;
     // Sanity check: Did we consume all 128 bytes?
-    assert_eq!(input.len(), 0);
+    let (_, _) = eof(input)?;
     #[allow(deprecated)]
-    Ok((
-        rest,
-        Header {
-            file_size,
-            frames,
-            width,
-            height,
-            color_depth,
-            flags,
-            speed,
-            transparent_index,
-            color_count,
-            pixel_width,
-            pixel_height,
-            grid_x,
-            grid_y,
-            grid_width,
-            grid_height,
-        },
-    ))
+    Ok(Header {
+        file_size,
+        frames,
+        width,
+        height,
+        color_depth,
+        flags,
+        speed,
+        transparent_index,
+        color_count,
+        pixel_width,
+        pixel_height,
+        grid_x,
+        grid_y,
+        grid_width,
+        grid_height,
+    })
 }
 
 #[test]
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use super::scalars::Word;
 
 #[derive(Clone, Debug)]
@@ -10,7 +12,19 @@ pub struct Image<'a> {
     /// for each scanline read pixels from left to right.
     /// --or--
     /// "Raw Cel" data compressed with ZLIB method (see NOTE.3)
-    pub data: &'a [u8],
+    pub data: Cow<'a, [u8]>,
     /// True if the cel data is compressed
     pub compressed: bool,
 }
+
+impl Image<'_> {
+    /// Convert into an image which does not borrow the input
+    pub fn into_owned(self) -> Image<'static> {
+        Image {
+            width: self.width,
+            height: self.height,
+            data: Cow::Owned(self.data.into_owned()),
+            compressed: self.compressed,
+        }
+    }
+}
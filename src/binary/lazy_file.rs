@@ -0,0 +1,243 @@
+use nom::{bytes::complete::take, error::ErrorKind};
+
+use super::{
+    chunk::{parse_chunks, parse_chunks_of_type, Chunk},
+    chunk_type::ChunkType,
+    chunks::{layer::LayerChunk, tags::Tag},
+    color_depth::ColorDepth,
+    errors::{ParseError, ParseErrorKind, ParseResult},
+    file::{next_user_data, tags_user_data},
+    header::{parse_header, Header},
+    palette::{create_palettes, Palette},
+    raw_frame::{parse_frame_header, RawFrame},
+    scalars::{dword_size, Word},
+};
+
+/// A frame of a [`LazyFile`] whose chunks are parsed on demand
+#[derive(Debug, Copy, Clone)]
+pub struct LazyFrame<'a> {
+    pub duration: Word,
+    /// Byte offset of the frame in the file
+    pub offset: usize,
+    /// Size of the frame in bytes including the frame header
+    pub size: usize,
+    pub chunk_count: usize,
+    /// The chunks following the frame header
+    chunks: &'a [u8],
+}
+
+/// A file of which only the header and the frame headers are parsed
+/// up front. The chunks of a frame are parsed when they are requested.
+///
+/// This is useful when only the metadata of a file is needed or when
+/// only single frames of a large file are accessed.
+#[derive(Debug)]
+pub struct LazyFile<'a> {
+    pub header: Header,
+    pub frames: Vec<LazyFrame<'a>>,
+    input: &'a [u8],
+}
+
+pub fn parse_lazy_file(input: &[u8]) -> Result<LazyFile<'_>, ParseError> {
+    let (mut rest, header) =
+        parse_header(input).map_err(|e| ParseError::from_nom(e).locate(input))?;
+    if rest.is_empty() {
+        return Err(ParseError::new(ParseErrorKind::Nom(ErrorKind::Many1), rest).locate(input));
+    }
+    let mut frames = Vec::new();
+    while !rest.is_empty() {
+        let offset = input.len() - rest.len();
+        let (next, frame) = parse_lazy_frame(rest, offset).map_err(|e| {
+            let mut e = ParseError::from_nom(e).locate(input);
+            e.frame = Some(frames.len());
+            e
+        })?;
+        frames.push(frame);
+        rest = next;
+    }
+    Ok(LazyFile {
+        header,
+        frames,
+        input,
+    })
+}
+
+fn parse_lazy_frame(input: &[u8], offset: usize) -> ParseResult<'_, LazyFrame<'_>> {
+    // The frame size includes the 16 bytes of the frame header
+    let (input, size) = dword_size(input, 16, ParseErrorKind::InvalidFrameSize)?;
+    let (rest, input) = take(size - 4)(input)?;
+    let size = input.len() + 4;
+    let (chunks, (duration, chunk_count)) =
+        parse_frame_header(input).map_err(|e| e.map(|e| e.followed_by(rest)))?;
+    Ok((
+        rest,
+        LazyFrame {
+            duration,
+            offset,
+            size,
+            chunk_count,
+            chunks,
+        },
+    ))
+}
+
+impl<'a> LazyFile<'a> {
+    /// Parse all chunks of the frame with the given index
+    pub fn parse_frame(&self, index: usize) -> Result<RawFrame<'a>, ParseError> {
+        self.parse_frame_with(index, |input, chunk_count| {
+            parse_chunks(input, chunk_count, None)
+        })
+    }
+    /// Parse the frame with the given index. Only the chunks of the given
+    /// types are parsed. All other chunks are returned as [`Chunk::Skipped`].
+    pub fn parse_frame_chunks(
+        &self,
+        index: usize,
+        chunk_types: &[ChunkType],
+    ) -> Result<RawFrame<'a>, ParseError> {
+        self.parse_frame_with(index, |input, chunk_count| {
+            parse_chunks_of_type(input, chunk_count, chunk_types)
+        })
+    }
+    fn parse_frame_with(
+        &self,
+        index: usize,
+        parse: impl FnOnce(&'a [u8], usize) -> ParseResult<'a, Vec<Chunk<'a>>>,
+    ) -> Result<RawFrame<'a>, ParseError> {
+        let frame = self.frames.get(index).ok_or_else(|| {
+            ParseError::without_offset(ParseErrorKind::FrameIndexOutOfBounds(index))
+        })?;
+        let (_, chunks) = parse(frame.chunks, frame.chunk_count).map_err(|e| {
+            let rest = &self.input[frame.offset + frame.size..];
+            let mut e = ParseError::from_nom(e).followed_by(rest).locate(self.input);
+            e.frame = Some(index);
+            e
+        })?;
+        Ok(RawFrame {
+            duration: frame.duration,
+            chunks,
+        })
+    }
+    /// Parse the layers of the file. Aseprite stores all layers in the
+    /// first frame so only that frame is parsed.
+    pub fn layers(&self) -> Result<Vec<LayerChunk<'a>>, ParseError> {
+        let frame = self.parse_frame_chunks(0, &[ChunkType::Layer, ChunkType::UserData])?;
+        let mut layers = Vec::new();
+        let mut chunks = frame.chunks.into_iter().peekable();
+        while let Some(chunk) = chunks.next() {
+            if let Chunk::Layer(layer) = chunk {
+                layers.push(LayerChunk {
+                    user_data: next_user_data(&mut chunks),
+                    ..layer
+                });
+            }
+        }
+        Ok(layers)
+    }
+    /// Parse the tags of the file. Aseprite stores all tags in the
+    /// first frame so only that frame is parsed.
+    pub fn tags(&self) -> Result<Vec<Tag<'a>>, ParseError> {
+        let frame = self.parse_frame_chunks(0, &[ChunkType::Tags, ChunkType::UserData])?;
+        let mut tags = Vec::new();
+        let mut chunks = frame.chunks.into_iter().peekable();
+        while let Some(chunk) = chunks.next() {
+            if let Chunk::Tags(mut tags_chunk) = chunk {
+                tags_user_data(&mut tags_chunk.tags, &mut chunks);
+                tags.extend(tags_chunk.tags);
+            }
+        }
+        Ok(tags)
+    }
+    /// Create the palette of indexed files. Palette chunks can be part
+    /// of any frame so the palette chunks of all frames are parsed.
+    pub fn palette(&self) -> Result<Option<Palette>, ParseError> {
+        Ok(self.palettes()?.pop().map(|(_, palette)| palette))
+    }
+    /// Create the palettes of indexed files together with the index of
+    /// the first frame using them. See [`File::palettes`](super::file::File::palettes).
+    pub fn palettes(&self) -> Result<Vec<(usize, Palette)>, ParseError> {
+        if self.header.color_depth != ColorDepth::Indexed {
+            return Ok(Vec::new());
+        }
+        let frames = (0..self.frames.len())
+            .map(|index| {
+                self.parse_frame_chunks(
+                    index,
+                    &[
+                        ChunkType::Palette0004,
+                        ChunkType::Palette0011,
+                        ChunkType::Palette,
+                    ],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        create_palettes(&frames)
+            .map_err(|e| ParseError::without_offset(ParseErrorKind::PaletteError(e)))
+    }
+}
+
+#[test]
+fn test_lazy_file() {
+    use super::file::parse_file;
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("aseprite") {
+            continue;
+        }
+        let input = std::fs::read(&path).unwrap();
+        let file = parse_file(&input).unwrap();
+        let lazy_file = parse_lazy_file(&input).unwrap();
+        assert_eq!(lazy_file.frames.len(), file.frames.len());
+        for (index, frame) in file.frames.iter().enumerate() {
+            assert_eq!(lazy_file.frames[index].duration, frame.duration);
+            let raw_frame = lazy_file.parse_frame(index).unwrap();
+            assert_eq!(
+                raw_frame.cels().count(),
+                frame.cels.iter().flatten().count()
+            );
+        }
+        let layers = lazy_file.layers().unwrap();
+        assert_eq!(layers.len(), file.layers.len());
+        for (layer, expected) in layers.iter().zip(&file.layers) {
+            assert_eq!(layer.name, expected.name);
+            assert_eq!(
+                layer.user_data.as_ref().and_then(|u| u.text.as_deref()),
+                expected.user_data.as_ref().and_then(|u| u.text.as_deref())
+            );
+        }
+        let tags = lazy_file.tags().unwrap();
+        assert_eq!(tags.len(), file.tags.len());
+        for (tag, expected) in tags.iter().zip(&file.tags) {
+            assert_eq!(tag.name, expected.name);
+            assert_eq!(
+                tag.user_data.as_ref().and_then(|u| u.text.as_deref()),
+                expected.user_data.as_ref().and_then(|u| u.text.as_deref())
+            );
+        }
+        let palette = lazy_file.palette().unwrap();
+        assert_eq!(
+            palette.map(|p| p.colors),
+            file.palette.as_ref().map(|p| p.colors)
+        );
+        let palettes = lazy_file.palettes().unwrap();
+        assert_eq!(format!("{:?}", palettes), format!("{:?}", file.palettes));
+    }
+}
+
+#[test]
+fn test_lazy_file_errors() {
+    let mut input = std::fs::read("./tests/layers.aseprite").unwrap();
+    let name = input.windows(7).position(|w| w == b"Layer 2").unwrap();
+    input[name] = 0xFF;
+    // The chunks are not parsed until they are requested
+    let lazy_file = parse_lazy_file(&input).unwrap();
+    assert_eq!(lazy_file.frames[0].offset, 128);
+    assert!(lazy_file.tags().is_ok());
+    let err = lazy_file.layers().unwrap_err();
+    assert!(matches!(err.kind, ParseErrorKind::Utf8Error(_)));
+    assert_eq!(err.offset, Some(name - 2));
+    assert_eq!(err.frame, Some(0));
+    assert_eq!(err.chunk_type, Some(ChunkType::Layer));
+    let err = lazy_file.parse_frame(lazy_file.frames.len()).unwrap_err();
+    assert!(matches!(err.kind, ParseErrorKind::FrameIndexOutOfBounds(_)));
+}
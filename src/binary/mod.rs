@@ -13,7 +13,10 @@ pub mod file;
 pub mod frame;
 pub mod header;
 pub mod image;
+pub mod lazy_file;
 pub mod palette;
+pub mod palette_file;
 pub mod raw_file;
 pub mod raw_frame;
 pub mod scalars;
+pub mod write;
@@ -1,109 +1,141 @@
+use std::collections::BTreeMap;
+
 use thiserror::Error;
 
 use super::{
     chunk::Chunk,
     chunks::{old_palette::OldPaletteChunk, palette::PaletteChunk},
-    header::Header,
     raw_frame::RawFrame,
     scalars::Color,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Palette {
     pub colors: [Color; 256],
+    /// Number of colors which are used. The remaining colors are unused.
+    pub color_count: usize,
+    /// Names of the colors by color index. Colors without a name are
+    /// not included.
+    pub names: BTreeMap<u8, String>,
 }
 
 impl Default for Palette {
     fn default() -> Self {
         Palette {
             colors: [Color::default(); 256],
+            color_count: 256,
+            names: BTreeMap::new(),
         }
     }
 }
 
-pub fn create_palette(header: &Header, frames: &[RawFrame<'_>]) -> Result<Palette, PaletteError> {
-    let mut palette = Palette::default();
-    let palette_chunks: Vec<_> = frames
-        .iter()
-        .flat_map(|frame| {
-            frame.chunks.iter().filter_map(|chunk| {
-                if let Chunk::Palette(palette) = chunk {
-                    Some(palette)
-                } else {
-                    None
-                }
-            })
-        })
-        .collect();
+/// Create the palette which is used by the last frame. This contains the
+/// changes of all palette chunks of the file.
+pub fn create_palette(frames: &[RawFrame<'_>]) -> Result<Palette, PaletteError> {
+    let mut palettes = create_palettes(frames)?;
+    Ok(palettes
+        .pop()
+        .map(|(_, palette)| palette)
+        .unwrap_or_default())
+}
+
+/// Create the palettes of all frames which contain palette chunks. Every
+/// entry contains the index of the first frame using the palette and is
+/// used until the next entry. Palette chunks only contain the changed
+/// colors so every palette includes the changes of all previous frames.
+///
+/// The color at the transparent index of the header keeps its alpha. It
+/// is only transparent in layers which are not the background layer.
+pub fn create_palettes(frames: &[RawFrame<'_>]) -> Result<Vec<(usize, Palette)>, PaletteError> {
+    let palette_chunks = frame_chunks(frames, |chunk| match chunk {
+        Chunk::Palette(palette) => Some(palette),
+        _ => None,
+    });
     if !palette_chunks.is_empty() {
-        process_palette_chunks(header.transparent_index, &palette_chunks, &mut palette)?;
-        return Ok(palette);
+        return process_palette_chunks(&palette_chunks);
     }
-    let palette0004_chunks: Vec<_> = frames
-        .iter()
-        .flat_map(|frame| {
-            frame.chunks.iter().filter_map(|chunk| {
-                if let Chunk::Palette0004(palette) = chunk {
-                    Some(palette)
-                } else {
-                    None
-                }
-            })
-        })
-        .collect();
+    let palette0004_chunks = frame_chunks(frames, |chunk| match chunk {
+        Chunk::Palette0004(palette) => Some(palette),
+        _ => None,
+    });
     if !palette0004_chunks.is_empty() {
-        process_old_palette_chunks(header.transparent_index, &palette0004_chunks, &mut palette)?;
-        return Ok(palette);
+        return process_old_palette_chunks(&palette0004_chunks);
     }
-    let palette0011_chunks: Vec<_> = frames
-        .iter()
-        .flat_map(|frame| {
-            frame.chunks.iter().filter_map(|chunk| {
-                if let Chunk::Palette0011(palette) = chunk {
-                    Some(palette)
-                } else {
-                    None
-                }
-            })
-        })
-        .collect();
+    let palette0011_chunks = frame_chunks(frames, |chunk| match chunk {
+        Chunk::Palette0011(palette) => Some(palette),
+        _ => None,
+    });
     if !palette0011_chunks.is_empty() {
-        process_old_palette_chunks(header.transparent_index, &palette0011_chunks, &mut palette)?;
-        return Ok(palette);
+        return process_old_palette_chunks(&palette0011_chunks);
     }
     Err(PaletteError::Missing)
 }
 
+/// Collect the chunks selected by `f` together with their frame index
+fn frame_chunks<'c, 'a: 'c, T>(
+    frames: &'c [RawFrame<'a>],
+    f: impl Fn(&'c Chunk<'a>) -> Option<&'c T>,
+) -> Vec<(usize, &'c T)> {
+    frames
+        .iter()
+        .enumerate()
+        .flat_map(|(frame_index, frame)| {
+            frame
+                .chunks
+                .iter()
+                .filter_map(&f)
+                .map(move |chunk| (frame_index, chunk))
+        })
+        .collect()
+}
+
+/// Store a copy of `palette` as the palette of the given frame
+fn push_palette(palettes: &mut Vec<(usize, Palette)>, frame_index: usize, palette: &Palette) {
+    match palettes.last_mut() {
+        Some((last_index, last)) if *last_index == frame_index => *last = palette.clone(),
+        _ => palettes.push((frame_index, palette.clone())),
+    }
+}
+
 fn process_palette_chunks(
-    transparent_index: u8,
-    chunks: &[&PaletteChunk<'_>],
-    palette: &mut Palette,
-) -> Result<(), PaletteError> {
+    chunks: &[(usize, &PaletteChunk<'_>)],
+) -> Result<Vec<(usize, Palette)>, PaletteError> {
+    let mut palette = Palette::default();
+    let mut palettes = Vec::new();
     let mut ok = false;
-    for chunk in chunks.iter() {
+    for (frame_index, chunk) in chunks.iter() {
         // The aseprite palette chunk is a bit weird. Both the palette size
         // and the color indices use `DWORD` (u32) as data type. Indexed
         // colors use `BYTE` (u8) though.
         for (entry, color_idx) in chunk.entries.iter().zip(chunk.indices.clone()) {
             palette.colors[usize::from(color_idx)] = entry.color;
+            let _ = match entry.name {
+                Some(name) => palette.names.insert(color_idx, name.to_owned()),
+                None => palette.names.remove(&color_idx),
+            };
             ok = true;
         }
+        palette.color_count = chunk.size.min(256);
+        push_palette(&mut palettes, *frame_index, &palette);
     }
     if ok {
-        palette.colors[usize::from(transparent_index)].alpha = 0;
-        Ok(())
+        Ok(palettes)
     } else {
         Err(PaletteError::Empty)
     }
 }
 
 fn process_old_palette_chunks(
-    transparent_index: u8,
-    chunks: &[&OldPaletteChunk],
-    palette: &mut Palette,
-) -> Result<(), PaletteError> {
+    chunks: &[(usize, &OldPaletteChunk)],
+) -> Result<Vec<(usize, Palette)>, PaletteError> {
+    // Old palette chunks don't contain the size of the palette
+    let mut palette = Palette {
+        color_count: 0,
+        ..Palette::default()
+    };
+    let mut palettes = Vec::new();
     let mut ok = false;
-    for chunk in chunks.iter() {
+    for (frame_index, chunk) in chunks.iter() {
         let mut color_idx = 0usize;
         for packet in &chunk.packets {
             color_idx += usize::from(packet.entries_to_skip);
@@ -120,11 +152,12 @@ fn process_old_palette_chunks(
                 color_idx += 1;
                 ok = true;
             }
+            palette.color_count = palette.color_count.max(color_idx);
         }
+        push_palette(&mut palettes, *frame_index, &palette);
     }
     if ok {
-        palette.colors[usize::from(transparent_index)].alpha = 0;
-        Ok(())
+        Ok(palettes)
     } else {
         Err(PaletteError::Empty)
     }
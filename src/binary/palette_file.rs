@@ -0,0 +1,552 @@
+//! This module contains the import and export of [`Palette`]s using the
+//! palette file formats of other applications.
+//!
+//! Imported palettes can be used to recolor sprites while rendering them
+//! via [`Recolor::Palette`](crate::loader::Recolor::Palette).
+
+use std::{fmt::Write, path::Path};
+
+use thiserror::Error;
+
+use super::{palette::Palette, scalars::Color};
+
+/// A palette file format
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PaletteFormat {
+    /// GIMP palette (`.gpl`). The names of the colors are kept. Palettes
+    /// with transparent colors use the `Channels: RGBA` extension of
+    /// Aseprite.
+    Gpl,
+    /// JASC palette (`.pal`) as written by Paint Shop Pro. This format
+    /// does not store the alpha of the colors.
+    JascPal,
+    /// One hexadecimal `rrggbb` color per line (`.hex`) as used by
+    /// Lospec. This format does not store the alpha of the colors.
+    Hex,
+    /// Adobe Color Table (`.act`). Only a single transparent color can
+    /// be stored.
+    Act,
+    /// PNG image with one pixel per color (`.png`). Exported images are
+    /// 1 pixel high. The pixels of imported images are read row by row.
+    #[cfg(feature = "png")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "png")))]
+    PngStrip,
+}
+
+impl PaletteFormat {
+    /// Get the palette format for the given file extension
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "gpl" => Some(Self::Gpl),
+            "pal" => Some(Self::JascPal),
+            "hex" => Some(Self::Hex),
+            "act" => Some(Self::Act),
+            #[cfg(feature = "png")]
+            "png" => Some(Self::PngStrip),
+            _ => None,
+        }
+    }
+
+    /// Get the palette format for the extension of the given path
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        Self::from_extension(path.as_ref().extension()?.to_str()?)
+    }
+}
+
+/// Errors that can occur while importing or exporting a palette file
+#[derive(Debug, Error)]
+pub enum PaletteFileError {
+    /// Reading or writing the file failed.
+    #[error("reading or writing failed")]
+    Io(#[from] std::io::Error),
+    /// The format can't be determined from the file extension.
+    #[error("unknown palette format")]
+    UnknownFormat,
+    /// The file does not start with the header of the format.
+    #[error("invalid palette header")]
+    InvalidHeader,
+    /// The color in the given line (starting at 1) can't be parsed.
+    #[error("invalid color in line {0}")]
+    InvalidColor(usize),
+    /// The file contains more than 256 colors.
+    #[error("too many colors: {0}")]
+    TooManyColors(usize),
+    /// The size of a binary palette file is invalid.
+    #[error("invalid palette size: {0} bytes")]
+    InvalidSize(usize),
+    /// Decoding the PNG image failed.
+    #[cfg(feature = "png")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "png")))]
+    #[error("decoding png failed")]
+    PngDecoding(#[from] png::DecodingError),
+    /// Encoding the PNG image failed.
+    #[cfg(feature = "png")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "png")))]
+    #[error("encoding png failed")]
+    PngEncoding(#[from] png::EncodingError),
+}
+
+impl Palette {
+    /// Import a palette from the contents of a palette file
+    pub fn import(data: &[u8], format: PaletteFormat) -> Result<Palette, PaletteFileError> {
+        match format {
+            PaletteFormat::Gpl => import_gpl(&String::from_utf8_lossy(data)),
+            PaletteFormat::JascPal => import_jasc_pal(&String::from_utf8_lossy(data)),
+            PaletteFormat::Hex => import_hex(&String::from_utf8_lossy(data)),
+            PaletteFormat::Act => import_act(data),
+            #[cfg(feature = "png")]
+            PaletteFormat::PngStrip => import_png(data),
+        }
+    }
+
+    /// Export the used colors of the palette into the given format
+    pub fn export(&self, format: PaletteFormat) -> Result<Vec<u8>, PaletteFileError> {
+        match format {
+            PaletteFormat::Gpl => Ok(self.export_gpl().into_bytes()),
+            PaletteFormat::JascPal => Ok(self.export_jasc_pal().into_bytes()),
+            PaletteFormat::Hex => Ok(self.export_hex().into_bytes()),
+            PaletteFormat::Act => Ok(self.export_act()),
+            #[cfg(feature = "png")]
+            PaletteFormat::PngStrip => self.export_png(),
+        }
+    }
+
+    /// Load a palette file. The format is determined by the file extension.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Palette, PaletteFileError> {
+        let format = PaletteFormat::from_path(&path).ok_or(PaletteFileError::UnknownFormat)?;
+        Self::import(&std::fs::read(path)?, format)
+    }
+
+    /// Save the palette to a file. The format is determined by the file
+    /// extension.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<(), PaletteFileError> {
+        let format = PaletteFormat::from_path(&path).ok_or(PaletteFileError::UnknownFormat)?;
+        std::fs::write(path, self.export(format)?)?;
+        Ok(())
+    }
+
+    /// The colors which are used by the palette
+    fn used_colors(&self) -> &[Color] {
+        &self.colors[..self.color_count.min(256)]
+    }
+
+    fn export_gpl(&self) -> String {
+        let mut out = String::from("GIMP Palette\n");
+        let alpha = self.used_colors().iter().any(|color| color.alpha != 255);
+        if alpha {
+            out.push_str("Channels: RGBA\n");
+        }
+        out.push_str("#\n");
+        for (index, color) in self.used_colors().iter().enumerate() {
+            let _ = write!(out, "{:3} {:3} {:3}", color.red, color.green, color.blue);
+            if alpha {
+                let _ = write!(out, " {:3}", color.alpha);
+            }
+            if let Some(name) = self.names.get(&(index as u8)) {
+                let _ = write!(out, "\t{}", name);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn export_jasc_pal(&self) -> String {
+        let colors = self.used_colors();
+        let mut out = format!("JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+        for color in colors {
+            let _ = write!(out, "{} {} {}\r\n", color.red, color.green, color.blue);
+        }
+        out
+    }
+
+    fn export_hex(&self) -> String {
+        let mut out = String::new();
+        for color in self.used_colors() {
+            let _ = writeln!(
+                out,
+                "{:02x}{:02x}{:02x}",
+                color.red, color.green, color.blue
+            );
+        }
+        out
+    }
+
+    fn export_act(&self) -> Vec<u8> {
+        let colors = self.used_colors();
+        let mut out = vec![0; 772];
+        for (rgb, color) in out.chunks_exact_mut(3).zip(colors) {
+            rgb.copy_from_slice(&[color.red, color.green, color.blue]);
+        }
+        let transparent_index = colors
+            .iter()
+            .position(|color| color.alpha == 0)
+            .map_or(0xFFFF, |index| index as u16);
+        out[768..770].copy_from_slice(&(colors.len() as u16).to_be_bytes());
+        out[770..772].copy_from_slice(&transparent_index.to_be_bytes());
+        out
+    }
+
+    #[cfg(feature = "png")]
+    fn export_png(&self) -> Result<Vec<u8>, PaletteFileError> {
+        let colors = self.used_colors();
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, colors.len() as u32, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let data: Vec<u8> = colors
+            .iter()
+            .flat_map(|color| [color.red, color.green, color.blue, color.alpha])
+            .collect();
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(out)
+    }
+}
+
+/// Create a palette from the given colors and names
+fn palette_from_colors(
+    colors: impl IntoIterator<Item = (Color, Option<String>)>,
+) -> Result<Palette, PaletteFileError> {
+    let mut palette = Palette {
+        color_count: 0,
+        ..Palette::default()
+    };
+    for (color, name) in colors {
+        let index = palette.color_count;
+        if index >= 256 {
+            return Err(PaletteFileError::TooManyColors(index + 1));
+        }
+        palette.colors[index] = color;
+        if let Some(name) = name {
+            let _ = palette.names.insert(index as u8, name);
+        }
+        palette.color_count += 1;
+    }
+    Ok(palette)
+}
+
+/// Parse the whitespace separated color channels at the start of `line`.
+/// Returns the color and the rest of the line.
+fn parse_channels(line: &str, channels: usize) -> Option<(Color, &str)> {
+    let mut values = [255; 4];
+    let mut rest = line;
+    for value in &mut values[..channels] {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        *value = rest[..end].parse().ok()?;
+        rest = &rest[end..];
+    }
+    let [red, green, blue, alpha] = values;
+    Some((
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        },
+        rest,
+    ))
+}
+
+fn import_gpl(text: &str) -> Result<Palette, PaletteFileError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => {}
+        _ => return Err(PaletteFileError::InvalidHeader),
+    }
+    let mut channels = 3;
+    let mut colors = Vec::new();
+    for (index, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Channels:") {
+            channels = match value.trim() {
+                "RGB" => 3,
+                "RGBA" => 4,
+                _ => return Err(PaletteFileError::InvalidHeader),
+            };
+            continue;
+        }
+        if line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let (color, name) =
+            parse_channels(line, channels).ok_or(PaletteFileError::InvalidColor(index + 1))?;
+        let name = Some(name.trim()).filter(|name| !name.is_empty());
+        colors.push((color, name.map(str::to_owned)));
+    }
+    palette_from_colors(colors)
+}
+
+fn import_jasc_pal(text: &str) -> Result<Palette, PaletteFileError> {
+    let line_count = text.lines().count();
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let mut header = || lines.next().map(|(_, line)| line);
+    if header() != Some("JASC-PAL") || header() != Some("0100") {
+        return Err(PaletteFileError::InvalidHeader);
+    }
+    let count: usize = header()
+        .and_then(|count| count.parse().ok())
+        .ok_or(PaletteFileError::InvalidHeader)?;
+    if count > 256 {
+        return Err(PaletteFileError::TooManyColors(count));
+    }
+    let mut colors = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (line_number, line) = lines
+            .next()
+            .ok_or(PaletteFileError::InvalidColor(line_count + 1))?;
+        // Some applications store the alpha as fourth value
+        let channels = line.split_whitespace().count();
+        let (color, _) = parse_channels(line, channels)
+            .filter(|_| channels == 3 || channels == 4)
+            .ok_or(PaletteFileError::InvalidColor(line_number))?;
+        colors.push((color, None));
+    }
+    palette_from_colors(colors)
+}
+
+fn import_hex(text: &str) -> Result<Palette, PaletteFileError> {
+    let mut colors = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let hex = line.strip_prefix('#').unwrap_or(line);
+        let value = Some(hex)
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or(PaletteFileError::InvalidColor(index + 1))?;
+        let [_, red, green, blue] = value.to_be_bytes();
+        let color = Color {
+            red,
+            green,
+            blue,
+            alpha: 255,
+        };
+        colors.push((color, None));
+    }
+    palette_from_colors(colors)
+}
+
+fn import_act(data: &[u8]) -> Result<Palette, PaletteFileError> {
+    // The color count and transparent index are optional
+    let (count, transparent_index) = match data.len() {
+        768 => (256, None),
+        772 => (
+            usize::from(u16::from_be_bytes([data[768], data[769]])),
+            Some(usize::from(u16::from_be_bytes([data[770], data[771]]))),
+        ),
+        size => return Err(PaletteFileError::InvalidSize(size)),
+    };
+    if count > 256 {
+        return Err(PaletteFileError::TooManyColors(count));
+    }
+    let colors = data[..count * 3]
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, rgb)| {
+            let color = Color {
+                red: rgb[0],
+                green: rgb[1],
+                blue: rgb[2],
+                alpha: if Some(index) == transparent_index {
+                    0
+                } else {
+                    255
+                },
+            };
+            (color, None)
+        });
+    palette_from_colors(colors)
+}
+
+#[cfg(feature = "png")]
+fn import_png(data: &[u8]) -> Result<Palette, PaletteFileError> {
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16 | png::Transformations::ALPHA,
+    );
+    let mut reader = decoder.read_info()?;
+    let info = reader.info();
+    let pixel_count = info.width as usize * info.height as usize;
+    if pixel_count > 256 {
+        return Err(PaletteFileError::TooManyColors(pixel_count));
+    }
+    let size = reader
+        .output_buffer_size()
+        .ok_or(PaletteFileError::TooManyColors(pixel_count))?;
+    let mut buf = vec![0; size];
+    let output = reader.next_frame(&mut buf)?;
+    let buf = &buf[..output.buffer_size()];
+    let colors: Vec<_> = match output.color_type {
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        _ => buf
+            .chunks_exact(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+            .collect(),
+    };
+    palette_from_colors(colors.into_iter().map(|[red, green, blue, alpha]| {
+        let color = Color {
+            red,
+            green,
+            blue,
+            alpha,
+        };
+        (color, None)
+    }))
+}
+
+#[cfg(test)]
+fn sample_palette() -> Palette {
+    let colors = [(255, 0, 0, 255), (0, 128, 0, 255), (1, 2, 3, 255)];
+    palette_from_colors(colors.into_iter().enumerate().map(|(i, (r, g, b, a))| {
+        let color = Color {
+            red: r,
+            green: g,
+            blue: b,
+            alpha: a,
+        };
+        (color, (i == 0).then(|| "Dark red".to_owned()))
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_gpl() {
+    let text = "GIMP Palette\nName: Test\nColumns: 4\n# comment\n\n255   0   0\tDark red\n  0 128   0\n1 2 3 Untitled\n";
+    let palette = Palette::import(text.as_bytes(), PaletteFormat::Gpl).unwrap();
+    assert_eq!(palette.color_count, 3);
+    assert_eq!(palette.colors[..3], sample_palette().colors[..3]);
+    assert_eq!(palette.names[&0], "Dark red");
+    assert_eq!(palette.names[&2], "Untitled");
+    assert!(!palette.names.contains_key(&1));
+
+    let palette = sample_palette();
+    let data = palette.export(PaletteFormat::Gpl).unwrap();
+    assert_eq!(Palette::import(&data, PaletteFormat::Gpl).unwrap(), palette);
+    // Transparent colors are stored using four channels
+    let mut palette = sample_palette();
+    palette.colors[1].alpha = 0;
+    let data = palette.export(PaletteFormat::Gpl).unwrap();
+    assert!(std::str::from_utf8(&data)
+        .unwrap()
+        .contains("Channels: RGBA"));
+    assert_eq!(Palette::import(&data, PaletteFormat::Gpl).unwrap(), palette);
+
+    assert!(matches!(
+        Palette::import(b"JASC-PAL\n", PaletteFormat::Gpl),
+        Err(PaletteFileError::InvalidHeader)
+    ));
+    assert!(matches!(
+        Palette::import(b"GIMP Palette\n1 2\n", PaletteFormat::Gpl),
+        Err(PaletteFileError::InvalidColor(2))
+    ));
+}
+
+#[test]
+fn test_jasc_pal() {
+    let mut palette = sample_palette();
+    let data = palette.export(PaletteFormat::JascPal).unwrap();
+    assert!(data.starts_with(b"JASC-PAL\r\n0100\r\n3\r\n255 0 0\r\n"));
+    palette.names.clear();
+    assert_eq!(
+        Palette::import(&data, PaletteFormat::JascPal).unwrap(),
+        palette
+    );
+    assert!(matches!(
+        Palette::import(b"JASC-PAL\n0100\n2\n1 2 3\n", PaletteFormat::JascPal),
+        Err(PaletteFileError::InvalidColor(5))
+    ));
+    assert!(matches!(
+        Palette::import(b"JASC-PAL\n0200\n0\n", PaletteFormat::JascPal),
+        Err(PaletteFileError::InvalidHeader)
+    ));
+}
+
+#[test]
+fn test_hex() {
+    let mut palette = sample_palette();
+    let data = palette.export(PaletteFormat::Hex).unwrap();
+    assert_eq!(data, b"ff0000\n008000\n010203\n");
+    palette.names.clear();
+    assert_eq!(Palette::import(&data, PaletteFormat::Hex).unwrap(), palette);
+    let palette = Palette::import(b"#FF0000\r\n", PaletteFormat::Hex).unwrap();
+    assert_eq!(palette.color_count, 1);
+    assert_eq!(palette.colors[0].red, 255);
+    assert!(matches!(
+        Palette::import(b"ff0000\nxyz\n", PaletteFormat::Hex),
+        Err(PaletteFileError::InvalidColor(2))
+    ));
+}
+
+#[test]
+fn test_act() {
+    let mut palette = sample_palette();
+    palette.names.clear();
+    palette.colors[2].alpha = 0;
+    let data = palette.export(PaletteFormat::Act).unwrap();
+    assert_eq!(data.len(), 772);
+    assert_eq!(data[768..], [0, 3, 0, 2]);
+    assert_eq!(Palette::import(&data, PaletteFormat::Act).unwrap(), palette);
+    // Files without color count contain 256 opaque colors
+    let palette = Palette::import(&data[..768], PaletteFormat::Act).unwrap();
+    assert_eq!(palette.color_count, 256);
+    assert_eq!(palette.colors[2].alpha, 255);
+    assert!(matches!(
+        Palette::import(&data[..100], PaletteFormat::Act),
+        Err(PaletteFileError::InvalidSize(100))
+    ));
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn test_png_strip() {
+    let mut palette = sample_palette();
+    palette.names.clear();
+    palette.colors[1].alpha = 128;
+    let data = palette.export(PaletteFormat::PngStrip).unwrap();
+    assert_eq!(
+        Palette::import(&data, PaletteFormat::PngStrip).unwrap(),
+        palette
+    );
+}
+
+#[test]
+fn test_palette_override() {
+    use crate::loader::{AsepriteFile, LayerSelection, Recolor, RenderOptions};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("palette.gpl");
+    std::fs::write(&path, "GIMP Palette\n1 1 1\n2 2 2\n3 3 3\n").unwrap();
+    let palette = Palette::load_from_path(&path).unwrap();
+    palette
+        .save_to_path(dir.path().join("palette.hex"))
+        .unwrap();
+    assert!(matches!(
+        palette.save_to_path(dir.path().join("palette.txt")),
+        Err(PaletteFileError::UnknownFormat)
+    ));
+
+    let data = std::fs::read("./tests/indexed_background.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let options = RenderOptions {
+        recolor: Some(Recolor::Palette(palette)),
+        ..Default::default()
+    };
+    let mut target = [0; 8];
+    file.render_frame_with_options(0, &mut target, &LayerSelection::Visible, &options)
+        .unwrap();
+    assert_eq!(target, [1, 1, 1, 255, 3, 3, 3, 255]);
+}
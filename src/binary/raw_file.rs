@@ -8,10 +8,44 @@ use super::{
 pub struct RawFile<'a> {
     pub header: Header,
     pub frames: Vec<RawFrame<'a>>,
+    /// Errors of the data which was skipped in lenient mode
+    pub diagnostics: Vec<ParseError>,
 }
 
-pub fn parse_raw_file(input: &[u8]) -> Result<RawFile<'_>, nom::Err<ParseError<'_>>> {
-    let (input, header) = parse_header(input)?;
-    let (_, frames) = parse_frames(input)?;
-    Ok(RawFile { header, frames })
+/// Options for parsing files
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Abort parsing at the first error. Otherwise chunks which fail to
+    /// parse are skipped and the errors are collected as diagnostics.
+    /// Errors in the file header are always fatal.
+    pub strict: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { strict: true }
+    }
+}
+
+pub fn parse_raw_file(input: &[u8]) -> Result<RawFile<'_>, ParseError> {
+    parse_raw_file_with_options(input, &ParseOptions::default())
+}
+
+pub fn parse_raw_file_with_options<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> Result<RawFile<'a>, ParseError> {
+    let (rest, header) = parse_header(input).map_err(|e| ParseError::from_nom(e).locate(input))?;
+    let mut diagnostics = Vec::new();
+    let (_, frames) = parse_frames(rest, (!options.strict).then_some(&mut diagnostics))
+        .map_err(|e| ParseError::from_nom(e).locate(input))?;
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|diagnostic| diagnostic.locate(input))
+        .collect();
+    Ok(RawFile {
+        header,
+        frames,
+        diagnostics,
+    })
 }
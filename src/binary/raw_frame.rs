@@ -1,7 +1,6 @@
 use nom::{
     bytes::complete::{tag, take},
-    combinator::{all_consuming, complete},
-    multi::many1,
+    error::{ErrorKind, ParseError as _},
     Parser,
 };
 
@@ -9,7 +8,7 @@ use super::{
     chunk::parse_chunks,
     chunk::Chunk,
     chunks::cel::CelChunk,
-    errors::{ParseError, ParseResult},
+    errors::{ParseError, ParseErrorKind, ParseResult},
     scalars::dword_size,
     scalars::Word,
     scalars::{parse_dword_as_usize, word},
@@ -35,14 +34,85 @@ impl RawFrame<'_> {
 
 const FRAME_MAGIC_NUMBER: [u8; 2] = 0xF1FAu16.to_le_bytes();
 
-pub fn parse_frames(input: &[u8]) -> ParseResult<'_, Vec<RawFrame<'_>>> {
-    complete(all_consuming(many1(parse_rawframe))).parse(input)
+/// Parse all frames. If `diagnostics` is given chunks which fail to parse
+/// are skipped and the errors are added to `diagnostics`. A frame which
+/// fails to parse ends the parsing as the start of the next frame is
+/// unknown.
+pub fn parse_frames<'a>(
+    input: &'a [u8],
+    mut diagnostics: Option<&mut Vec<ParseError>>,
+) -> ParseResult<'a, Vec<RawFrame<'a>>> {
+    if input.is_empty() {
+        return Err(nom::Err::Error(ParseError::from_error_kind(
+            input,
+            ErrorKind::Many1,
+        )));
+    }
+    let mut frames = Vec::new();
+    let mut input = input;
+    while !input.is_empty() {
+        let index = frames.len();
+        let first_diagnostic = diagnostics.as_ref().map_or(0, |d| d.len());
+        let result = parse_rawframe(input, diagnostics.as_deref_mut());
+        if let Some(diagnostics) = diagnostics.as_deref_mut() {
+            for diagnostic in &mut diagnostics[first_diagnostic..] {
+                diagnostic.frame = Some(index);
+            }
+        }
+        let result = result.map_err(|e| {
+            e.map(|mut e| {
+                e.frame = Some(index);
+                e
+            })
+        });
+        match (result, diagnostics.as_deref_mut()) {
+            (Ok((rest, frame)), _) => {
+                frames.push(frame);
+                input = rest;
+            }
+            (Err(e), None) => return Err(e),
+            (Err(e), Some(diagnostics)) => {
+                diagnostics.push(ParseError::from_nom(e));
+                break;
+            }
+        }
+    }
+    Ok((input, frames))
 }
 
-pub fn parse_rawframe(input: &[u8]) -> ParseResult<'_, RawFrame<'_>> {
-    let (input, size) = dword_size(input, ParseError::InvalidFrameSize)?;
-    // FIXME handle underflows
+/// Parse a single frame. See [`parse_frames`] for a description of
+/// `diagnostics`.
+pub fn parse_rawframe<'a>(
+    input: &'a [u8],
+    mut diagnostics: Option<&mut Vec<ParseError>>,
+) -> ParseResult<'a, RawFrame<'a>> {
+    // The frame size includes the 16 bytes of the frame header
+    let (input, size) = dword_size(input, 16, ParseErrorKind::InvalidFrameSize)?;
     let (rest, input) = take(size - 4)(input)?;
+    let first_diagnostic = diagnostics.as_ref().map_or(0, |d| d.len());
+    let result = parse_frame_data(input, diagnostics.as_deref_mut());
+    if let Some(diagnostics) = diagnostics {
+        for diagnostic in &mut diagnostics[first_diagnostic..] {
+            *diagnostic = diagnostic.followed_by(rest);
+        }
+    }
+    result
+        .map(|frame| (rest, frame))
+        .map_err(|e| e.map(|e| e.followed_by(rest)))
+}
+
+fn parse_frame_data<'a>(
+    input: &'a [u8],
+    diagnostics: Option<&mut Vec<ParseError>>,
+) -> Result<RawFrame<'a>, nom::Err<ParseError>> {
+    let (input, (duration, chunk_count)) = parse_frame_header(input)?;
+    let (_, chunks) = parse_chunks(input, chunk_count, diagnostics)?;
+    Ok(RawFrame { duration, chunks })
+}
+
+/// Parse the frame header following the frame size. Returns the
+/// duration and the number of chunks of the frame.
+pub fn parse_frame_header(input: &[u8]) -> ParseResult<'_, (Word, usize)> {
     let (input, _) = tag(FRAME_MAGIC_NUMBER.as_slice()).parse(input)?;
     let (input, chunk_count) = word(input)?;
     let (input, duration) = word(input)?;
@@ -51,6 +121,5 @@ pub fn parse_rawframe(input: &[u8]) -> ParseResult<'_, RawFrame<'_>> {
         (input, 0) => (input, chunk_count.into()),
         (input, chunk_count) => (input, chunk_count),
     };
-    let (_, chunks) = parse_chunks(input, chunk_count)?;
-    Ok((rest, RawFrame { duration, chunks }))
+    Ok((input, (duration, chunk_count)))
 }
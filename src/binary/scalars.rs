@@ -13,7 +13,7 @@ use nom::{
     Parser,
 };
 
-use super::errors::{ParseError, ParseResult};
+use super::errors::{ParseError, ParseErrorKind, ParseResult};
 
 pub type Byte = u8;
 pub type Word = u16;
@@ -106,30 +106,34 @@ pub fn parse_rect(input: &[u8]) -> ParseResult<'_, Rect> {
 }
 
 /// Parse a DWORD as size information and make sure the
-/// parsed size no less than 4. The latter is important as
-/// this function is used when parsing frames and chunks
-/// where the size includes itself.
-pub fn dword_size<'a>(input: &'a [u8], f: fn(Dword) -> ParseError<'a>) -> ParseResult<'a, Dword> {
-    let (input, size) = dword(input)?;
-    if size >= 4 {
-        Ok((input, size))
+/// parsed size no less than `min_size`. The latter is important
+/// as this function is used when parsing frames and chunks
+/// where the size includes itself and the following header.
+pub fn dword_size(
+    input: &[u8],
+    min_size: Dword,
+    f: fn(Dword) -> ParseErrorKind,
+) -> ParseResult<'_, Dword> {
+    let (rest, size) = dword(input)?;
+    if size >= min_size {
+        Ok((rest, size))
     } else {
-        Err(nom::Err::Failure(f(size)))
+        Err(ParseError::failure(f(size), input))
     }
 }
 
 pub fn parse_dword_as_usize(input: &[u8]) -> ParseResult<'_, usize> {
-    let (input, size) = dword(input)?;
+    let (rest, size) = dword(input)?;
     let size = size
         .try_into()
-        .map_err(|_| nom::Err::Failure(ParseError::DwordToUsize(size)))?;
-    Ok((input, size))
+        .map_err(|_| ParseError::failure(ParseErrorKind::DwordToUsize(size), input))?;
+    Ok((rest, size))
 }
 
-pub fn parse_dword_as_u8<'a>(input: &'a [u8], e: ParseError<'a>) -> ParseResult<'a, u8> {
-    let (input, size) = dword(input)?;
-    let size = size.try_into().map_err(|_| nom::Err::Failure(e))?;
-    Ok((input, size))
+pub fn parse_dword_as_u8(input: &[u8], e: ParseErrorKind) -> ParseResult<'_, u8> {
+    let (rest, size) = dword(input)?;
+    let size = size.try_into().map_err(|_| ParseError::failure(e, input))?;
+    Ok((rest, size))
 }
 
 pub fn parse_string(input: &[u8]) -> ParseResult<'_, &str> {
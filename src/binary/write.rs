@@ -0,0 +1,769 @@
+//! This module contains a writer which serializes a [`File`] back into
+//! the .aseprite file format. Parsing the written data results in a
+//! [`File`] which is equivalent to the original one.
+//!
+//! Only data which is part of the [`File`] structure is written. Chunks
+//! which are skipped by [`parse_file`](super::file::parse_file) (e.g. color
+//! profiles and external files) are lost.
+
+use super::{
+    blend_mode::BlendMode,
+    chunk_type::ChunkType,
+    chunks::{
+        cel::{CelChunk, CelContent},
+        layer::{LayerChunk, LayerType},
+        palette::PaletteEntryFlags,
+        slice::{NinePatch, SliceChunk, SliceFlags},
+        tags::{AnimationDirection, Tag},
+        tileset::{TilesetChunk, TilesetFlags},
+        user_data::{PropertiesMap, PropertyType, UserDataChunk, UserDataFlags, Value, Vector},
+    },
+    errors::WriteError,
+    file::File,
+    frame::Frame,
+    header::Header,
+    palette::Palette,
+    scalars::{Byte, Color, Dword, Fixed, Long, Point, Rect, Short, Size, Word},
+};
+
+const HEADER_MAGIC_NUMBER: Word = 0xA5E0;
+const FRAME_MAGIC_NUMBER: Word = 0xF1FA;
+
+/// Serialize a [`File`] into the .aseprite file format.
+pub fn write_file(file: &File<'_>) -> Result<Vec<u8>, WriteError> {
+    if file.frames.is_empty() {
+        return Err(WriteError::NoFrames);
+    }
+    let frame_count = Word::try_from(file.frames.len())
+        .map_err(|_| WriteError::TooManyFrames(file.frames.len()))?;
+    let mut out = Writer::default();
+    for (frame_index, frame) in file.frames.iter().enumerate() {
+        let mut chunks = Vec::new();
+        if frame_index == 0 {
+            write_sprite_chunks(file, &mut chunks)?;
+        } else {
+            // Palettes which change in later frames
+            for (_, palette) in file.palettes.iter().filter(|(i, _)| *i == frame_index) {
+                chunk(&mut chunks, ChunkType::Palette, |out| {
+                    write_palette_chunk(out, palette, file.header.color_count)
+                })?;
+            }
+        }
+        write_cel_chunks(frame, &mut chunks)?;
+        write_frame(&mut out, frame.duration, &chunks)?;
+    }
+    let frames = out.0;
+    let mut out = Writer::default();
+    write_header(&mut out, &file.header, frame_count, frames.len())?;
+    out.0.extend(frames);
+    Ok(out.0)
+}
+
+/// A little endian writer using the same names for the scalar types
+/// as the specification.
+#[derive(Debug, Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn byte(&mut self, value: Byte) {
+        self.0.push(value);
+    }
+    fn word(&mut self, value: Word) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn short(&mut self, value: Short) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn dword(&mut self, value: Dword) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn long(&mut self, value: Long) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn zeros(&mut self, count: usize) {
+        self.0.resize(self.0.len() + count, 0);
+    }
+    fn bytes(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+    fn dword_size(&mut self, size: usize) -> Result<(), WriteError> {
+        self.dword(Dword::try_from(size).map_err(|_| WriteError::SizeOverflow(size))?);
+        Ok(())
+    }
+    fn string(&mut self, value: &str) -> Result<(), WriteError> {
+        let len =
+            Word::try_from(value.len()).map_err(|_| WriteError::StringTooLong(value.len()))?;
+        self.word(len);
+        self.bytes(value.as_bytes());
+        Ok(())
+    }
+    fn color(&mut self, color: Color) {
+        self.bytes(&[color.red, color.green, color.blue, color.alpha]);
+    }
+    fn fixed(&mut self, value: Fixed) {
+        // The parser stores the integer part first
+        self.word(value.1);
+        self.word(value.0);
+    }
+    fn point(&mut self, value: Point) {
+        self.long(value.x);
+        self.long(value.y);
+    }
+    fn size(&mut self, value: Size) {
+        self.long(value.width);
+        self.long(value.height);
+    }
+    fn rect(&mut self, value: Rect) {
+        self.point(value.point);
+        self.size(value.size);
+    }
+}
+
+fn write_header(
+    out: &mut Writer,
+    header: &Header,
+    frame_count: Word,
+    frames_size: usize,
+) -> Result<(), WriteError> {
+    out.dword_size(128 + frames_size)?;
+    out.word(HEADER_MAGIC_NUMBER);
+    out.word(frame_count);
+    out.word(header.width);
+    out.word(header.height);
+    out.word(header.color_depth.bpp());
+    out.dword(header.flags);
+    #[allow(deprecated)]
+    out.word(header.speed);
+    out.zeros(8);
+    out.byte(header.transparent_index);
+    out.zeros(3);
+    out.word(header.color_count);
+    out.byte(header.pixel_width);
+    out.byte(header.pixel_height);
+    out.short(header.grid_x);
+    out.short(header.grid_y);
+    out.word(header.grid_width);
+    out.word(header.grid_height);
+    out.zeros(84);
+    Ok(())
+}
+
+fn write_frame(out: &mut Writer, duration: Word, chunks: &[Vec<u8>]) -> Result<(), WriteError> {
+    let size = 16 + chunks.iter().map(Vec::len).sum::<usize>();
+    out.dword_size(size)?;
+    out.word(FRAME_MAGIC_NUMBER);
+    out.word(Word::try_from(chunks.len()).unwrap_or(Word::MAX));
+    out.word(duration);
+    out.zeros(2);
+    out.dword_size(chunks.len())?;
+    for chunk in chunks {
+        out.bytes(chunk);
+    }
+    Ok(())
+}
+
+/// Create a chunk by prepending the chunk header to the chunk data
+/// written by `f`.
+fn chunk(
+    chunks: &mut Vec<Vec<u8>>,
+    chunk_type: ChunkType,
+    f: impl FnOnce(&mut Writer) -> Result<(), WriteError>,
+) -> Result<(), WriteError> {
+    let mut data = Writer::default();
+    f(&mut data)?;
+    let mut out = Writer::default();
+    out.dword_size(data.0.len() + 6)?;
+    out.word(chunk_type as Word);
+    out.bytes(&data.0);
+    chunks.push(out.0);
+    Ok(())
+}
+
+/// Write all chunks which are stored in the first frame and apply to the
+/// whole sprite.
+fn write_sprite_chunks(file: &File<'_>, chunks: &mut Vec<Vec<u8>>) -> Result<(), WriteError> {
+    // Every sprite has a palette. It is also needed as anchor for the
+    // user data of the sprite.
+    let default_palette;
+    let first_palette = file.palettes.first().filter(|(i, _)| *i == 0);
+    let palette = match (first_palette, &file.palette) {
+        (Some((_, palette)), _) | (None, Some(palette)) => palette,
+        (None, None) => {
+            default_palette = Palette::default();
+            &default_palette
+        }
+    };
+    chunk(chunks, ChunkType::Palette, |out| {
+        write_palette_chunk(out, palette, file.header.color_count)
+    })?;
+    if let Some(user_data) = &file.user_data {
+        write_user_data_chunk(chunks, user_data)?;
+    }
+    for tileset in &file.tilesets {
+        chunk(chunks, ChunkType::Tileset, |out| {
+            write_tileset_chunk(out, tileset)
+        })?;
+    }
+    for layer in &file.layers {
+        chunk(chunks, ChunkType::Layer, |out| {
+            write_layer_chunk(out, layer)
+        })?;
+        if let Some(user_data) = &layer.user_data {
+            write_user_data_chunk(chunks, user_data)?;
+        }
+    }
+    if !file.tags.is_empty() {
+        chunk(chunks, ChunkType::Tags, |out| {
+            write_tags_chunk(out, &file.tags)
+        })?;
+        // The user data chunks following the tags chunk are assigned to
+        // the tags in order. Tags without user data get an empty user data
+        // chunk if a following tag has user data.
+        let count = file
+            .tags
+            .iter()
+            .rposition(|tag| tag.user_data.is_some())
+            .map_or(0, |i| i + 1);
+        for tag in &file.tags[..count] {
+            match &tag.user_data {
+                Some(user_data) => write_user_data_chunk(chunks, user_data)?,
+                None => write_user_data_chunk(
+                    chunks,
+                    &UserDataChunk {
+                        text: None,
+                        color: None,
+                        properties_maps: None,
+                    },
+                )?,
+            }
+        }
+    }
+    for slice in &file.slices {
+        chunk(chunks, ChunkType::Slice, |out| {
+            write_slice_chunk(out, slice)
+        })?;
+        if let Some(user_data) = &slice.user_data {
+            write_user_data_chunk(chunks, user_data)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_cel_chunks(frame: &Frame<'_>, chunks: &mut Vec<Vec<u8>>) -> Result<(), WriteError> {
+    for cel in frame.cels.iter().flatten() {
+        chunk(chunks, ChunkType::Cel, |out| write_cel_chunk(out, cel))?;
+        if let Some(user_data) = &cel.user_data {
+            write_user_data_chunk(chunks, user_data)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_palette_chunk(
+    out: &mut Writer,
+    palette: &Palette,
+    color_count: Word,
+) -> Result<(), WriteError> {
+    // A color count of 0 means 256 for old sprites
+    let color_count = match usize::from(color_count) {
+        0 => 256,
+        n => n.min(256),
+    };
+    out.dword_size(color_count)?;
+    out.dword(0);
+    out.dword_size(color_count - 1)?;
+    out.zeros(8);
+    for (index, color) in palette.colors[..color_count].iter().enumerate() {
+        match palette.names.get(&(index as u8)) {
+            Some(name) => {
+                out.word(PaletteEntryFlags::HAS_NAME.bits());
+                out.color(*color);
+                out.string(name)?;
+            }
+            None => {
+                out.word(0);
+                out.color(*color);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_layer_chunk(out: &mut Writer, layer: &LayerChunk<'_>) -> Result<(), WriteError> {
+    out.word(layer.flags.bits());
+    out.word(match layer.layer_type {
+        LayerType::Normal => 0,
+        LayerType::Group => 1,
+        LayerType::Tilemap => 2,
+        LayerType::Unknown(n) => n,
+    });
+    out.word(layer.child_level);
+    // Default layer width and height are ignored
+    out.zeros(4);
+    out.word(blend_mode_to_word(layer.blend_mode));
+    out.byte(layer.opacity);
+    out.zeros(3);
+    out.string(&layer.name)?;
+    if layer.layer_type == LayerType::Tilemap {
+        out.dword(layer.tileset_index.unwrap_or_default());
+    }
+    Ok(())
+}
+
+fn write_cel_chunk(out: &mut Writer, cel: &CelChunk<'_>) -> Result<(), WriteError> {
+    out.word(cel.layer_index);
+    out.short(cel.x);
+    out.short(cel.y);
+    out.byte(cel.opacity);
+    out.word(match &cel.content {
+        CelContent::Image(image) if !image.compressed => 0,
+        CelContent::LinkedCel { .. } => 1,
+        CelContent::Image(_) => 2,
+        CelContent::CompressedTilemap { .. } => 3,
+        CelContent::Unknown { cel_type, .. } => *cel_type,
+    });
+    out.short(cel.z_index);
+    out.zeros(5);
+    match &cel.content {
+        CelContent::Image(image) => {
+            out.word(image.width);
+            out.word(image.height);
+            out.bytes(&image.data);
+        }
+        CelContent::LinkedCel { frame_position } => out.word(*frame_position),
+        CelContent::CompressedTilemap {
+            width,
+            height,
+            bits_per_tile,
+            bitmask_tile_id,
+            bitmask_x_flip,
+            bitmask_y_flip,
+            bitmask_diagonal_flip,
+            data,
+        } => {
+            out.word(*width);
+            out.word(*height);
+            out.word(*bits_per_tile);
+            out.dword(*bitmask_tile_id);
+            out.dword(*bitmask_x_flip);
+            out.dword(*bitmask_y_flip);
+            out.dword(*bitmask_diagonal_flip);
+            out.zeros(10);
+            out.bytes(data);
+        }
+        CelContent::Unknown { data, .. } => out.bytes(data),
+    }
+    Ok(())
+}
+
+fn write_tags_chunk(out: &mut Writer, tags: &[Tag<'_>]) -> Result<(), WriteError> {
+    out.word(Word::try_from(tags.len()).map_err(|_| WriteError::SizeOverflow(tags.len()))?);
+    out.zeros(8);
+    for tag in tags {
+        out.word(*tag.frames.start());
+        out.word(*tag.frames.end());
+        out.byte(match tag.animation_direction {
+            AnimationDirection::Forward => 0,
+            AnimationDirection::Reverse => 1,
+            AnimationDirection::PingPong => 2,
+            AnimationDirection::PingPongReverse => 3,
+            AnimationDirection::Unknown(n) => n,
+        });
+        out.word(tag.animation_repeat);
+        out.zeros(6);
+        #[allow(deprecated)]
+        out.bytes(&tag.color);
+        out.zeros(1);
+        out.string(&tag.name)?;
+    }
+    Ok(())
+}
+
+fn write_slice_chunk(out: &mut Writer, slice: &SliceChunk<'_>) -> Result<(), WriteError> {
+    out.dword_size(slice.slice_keys.len())?;
+    out.dword(slice.flags.bits());
+    out.dword(0);
+    out.string(&slice.name)?;
+    for key in &slice.slice_keys {
+        out.dword(key.frame_number);
+        out.long(key.x);
+        out.long(key.y);
+        out.dword(key.width);
+        out.dword(key.height);
+        if slice.flags.contains(SliceFlags::NINE_PATCH) {
+            let nine_patch = key.nine_patch.unwrap_or(NinePatch {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            });
+            out.long(nine_patch.x);
+            out.long(nine_patch.y);
+            out.dword(nine_patch.width);
+            out.dword(nine_patch.height);
+        }
+        if slice.flags.contains(SliceFlags::PIVOT) {
+            let (x, y) = key.pivot.map_or((0, 0), |pivot| (pivot.x, pivot.y));
+            out.long(x);
+            out.long(y);
+        }
+    }
+    Ok(())
+}
+
+fn write_tileset_chunk(out: &mut Writer, tileset: &TilesetChunk<'_>) -> Result<(), WriteError> {
+    // The flags must match the optional fields which are written
+    let mut flags = TilesetFlags::from_bits_retain(tileset.flags.bits());
+    flags.set(TilesetFlags::EXTERNAL_FILE, tileset.external_file.is_some());
+    flags.set(TilesetFlags::TILES, tileset.tiles.is_some());
+    out.dword(tileset.id);
+    out.dword(flags.bits());
+    out.dword(tileset.number_of_tiles);
+    out.word(tileset.width);
+    out.word(tileset.height);
+    out.short(tileset.base_index);
+    out.zeros(14);
+    out.string(&tileset.name)?;
+    if let Some(external_file) = &tileset.external_file {
+        out.dword(external_file.external_file_id);
+        out.dword(external_file.tileset_id);
+    }
+    if let Some(tiles) = &tileset.tiles {
+        out.dword_size(tiles.data.len())?;
+        out.bytes(&tiles.data);
+    }
+    Ok(())
+}
+
+fn write_user_data_chunk(
+    chunks: &mut Vec<Vec<u8>>,
+    user_data: &UserDataChunk<'_>,
+) -> Result<(), WriteError> {
+    let properties_maps = match &user_data.properties_maps {
+        Some(Ok((_, maps))) => Some(maps),
+        Some(Err(_)) => return Err(WriteError::InvalidProperties),
+        None => None,
+    };
+    chunk(chunks, ChunkType::UserData, |out| {
+        let mut flags = UserDataFlags::empty();
+        flags.set(UserDataFlags::HAS_TEXT, user_data.text.is_some());
+        flags.set(UserDataFlags::HAS_COLOR, user_data.color.is_some());
+        flags.set(UserDataFlags::HAS_PROPERTIES, properties_maps.is_some());
+        out.dword(flags.bits());
+        if let Some(text) = &user_data.text {
+            out.string(text)?;
+        }
+        if let Some(color) = user_data.color {
+            out.color(color);
+        }
+        if let Some(maps) = properties_maps {
+            let mut data = Writer::default();
+            for map in maps {
+                write_properties_map(&mut data, map)?;
+            }
+            out.dword_size(data.0.len() + 8)?;
+            out.dword_size(maps.len())?;
+            out.bytes(&data.0);
+        }
+        Ok(())
+    })
+}
+
+fn write_properties_map(out: &mut Writer, map: &PropertiesMap<'_>) -> Result<(), WriteError> {
+    out.dword(map.extension_entry_id);
+    out.dword_size(map.properties.len())?;
+    for property in &map.properties {
+        out.string(&property.name)?;
+        write_value(out, &property.value)?;
+    }
+    Ok(())
+}
+
+fn write_value(out: &mut Writer, value: &Value<'_>) -> Result<(), WriteError> {
+    match value {
+        Value::Bool(v) => {
+            out.word(PropertyType::Bool as Word);
+            out.byte((*v).into());
+        }
+        Value::Int8(v) => {
+            out.word(PropertyType::Int8 as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+        Value::Uint8(v) => {
+            out.word(PropertyType::Uint8 as Word);
+            out.byte(*v);
+        }
+        Value::Int16(v) => {
+            out.word(PropertyType::Int16 as Word);
+            out.short(*v);
+        }
+        Value::Uint16(v) => {
+            out.word(PropertyType::Uint16 as Word);
+            out.word(*v);
+        }
+        Value::Int32(v) => {
+            out.word(PropertyType::Int32 as Word);
+            out.long(*v);
+        }
+        Value::Uint32(v) => {
+            out.word(PropertyType::Uint32 as Word);
+            out.dword(*v);
+        }
+        Value::Int64(v) => {
+            out.word(PropertyType::Int64 as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+        Value::Uint64(v) => {
+            out.word(PropertyType::Uint64 as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+        Value::Fixed(v) => {
+            out.word(PropertyType::Fixed as Word);
+            out.fixed(*v);
+        }
+        Value::Float(v) => {
+            out.word(PropertyType::Float as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+        Value::Double(v) => {
+            out.word(PropertyType::Double as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+        Value::String(v) => {
+            out.word(PropertyType::String as Word);
+            out.string(v)?;
+        }
+        Value::Point(v) => {
+            out.word(PropertyType::Point as Word);
+            out.point(*v);
+        }
+        Value::Size(v) => {
+            out.word(PropertyType::Size as Word);
+            out.size(*v);
+        }
+        Value::Rect(v) => {
+            out.word(PropertyType::Rect as Word);
+            out.rect(*v);
+        }
+        Value::Vector(v) => {
+            out.word(PropertyType::Vector as Word);
+            write_vector(out, v)?;
+        }
+        Value::MixedVector(v) => {
+            out.word(PropertyType::Vector as Word);
+            write_mixed_vector(out, v)?;
+        }
+        Value::PropertiesMap(v) => {
+            out.word(PropertyType::PropertiesMap as Word);
+            write_properties_map(out, v)?;
+        }
+        Value::Uuid(v) => {
+            out.word(PropertyType::Uuid as Word);
+            out.bytes(&v.to_le_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn write_mixed_vector(out: &mut Writer, values: &[Value<'_>]) -> Result<(), WriteError> {
+    out.dword_size(values.len())?;
+    out.word(0);
+    for value in values {
+        write_value(out, value)?;
+    }
+    Ok(())
+}
+
+fn write_vector(out: &mut Writer, vector: &Vector<'_>) -> Result<(), WriteError> {
+    /// Write the vector header followed by all elements.
+    fn elements<T>(
+        out: &mut Writer,
+        prop_type: PropertyType,
+        values: &[T],
+        mut f: impl FnMut(&mut Writer, &T) -> Result<(), WriteError>,
+    ) -> Result<(), WriteError> {
+        out.dword_size(values.len())?;
+        out.word(prop_type as Word);
+        for value in values {
+            f(out, value)?;
+        }
+        Ok(())
+    }
+    match vector {
+        Vector::Mixed(v) => write_mixed_vector(out, v),
+        Vector::Bool(v) => elements(out, PropertyType::Bool, v, |o, v| {
+            o.byte((*v).into());
+            Ok(())
+        }),
+        Vector::Int8(v) => elements(out, PropertyType::Int8, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+        Vector::Uint8(v) => elements(out, PropertyType::Uint8, v, |o, v| {
+            o.byte(*v);
+            Ok(())
+        }),
+        Vector::Int16(v) => elements(out, PropertyType::Int16, v, |o, v| {
+            o.short(*v);
+            Ok(())
+        }),
+        Vector::Uint16(v) => elements(out, PropertyType::Uint16, v, |o, v| {
+            o.word(*v);
+            Ok(())
+        }),
+        Vector::Int32(v) => elements(out, PropertyType::Int32, v, |o, v| {
+            o.long(*v);
+            Ok(())
+        }),
+        Vector::Uint32(v) => elements(out, PropertyType::Uint32, v, |o, v| {
+            o.dword(*v);
+            Ok(())
+        }),
+        Vector::Int64(v) => elements(out, PropertyType::Int64, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+        Vector::Uint64(v) => elements(out, PropertyType::Uint64, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+        Vector::Fixed(v) => elements(out, PropertyType::Fixed, v, |o, v| {
+            o.fixed(*v);
+            Ok(())
+        }),
+        Vector::Float(v) => elements(out, PropertyType::Float, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+        Vector::Double(v) => elements(out, PropertyType::Double, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+        Vector::String(v) => elements(out, PropertyType::String, v, |o, v| o.string(v)),
+        Vector::Point(v) => elements(out, PropertyType::Point, v, |o, v| {
+            o.point(*v);
+            Ok(())
+        }),
+        Vector::Size(v) => elements(out, PropertyType::Size, v, |o, v| {
+            o.size(*v);
+            Ok(())
+        }),
+        Vector::Rect(v) => elements(out, PropertyType::Rect, v, |o, v| {
+            o.rect(*v);
+            Ok(())
+        }),
+        Vector::Vector(v) => elements(out, PropertyType::Vector, v, write_vector),
+        Vector::PropertiesMap(v) => {
+            elements(out, PropertyType::PropertiesMap, v, write_properties_map)
+        }
+        Vector::Uuid(v) => elements(out, PropertyType::Uuid, v, |o, v| {
+            o.bytes(&v.to_le_bytes());
+            Ok(())
+        }),
+    }
+}
+
+fn blend_mode_to_word(blend_mode: BlendMode) -> Word {
+    match blend_mode {
+        BlendMode::Normal => 0,
+        BlendMode::Multiply => 1,
+        BlendMode::Screen => 2,
+        BlendMode::Overlay => 3,
+        BlendMode::Darken => 4,
+        BlendMode::Lighten => 5,
+        BlendMode::ColorDodge => 6,
+        BlendMode::ColorBurn => 7,
+        BlendMode::HardLight => 8,
+        BlendMode::SoftLight => 9,
+        BlendMode::Difference => 10,
+        BlendMode::Exclusion => 11,
+        BlendMode::Hue => 12,
+        BlendMode::Saturation => 13,
+        BlendMode::Color => 14,
+        BlendMode::Luminosity => 15,
+        BlendMode::Addition => 16,
+        BlendMode::Subtract => 17,
+        BlendMode::Divide => 18,
+        BlendMode::Unknown(n) => n,
+    }
+}
+
+#[test]
+fn test_blend_mode_to_word() {
+    for word in (0..=18).chain([0x1337]) {
+        assert_eq!(blend_mode_to_word(BlendMode::from(word)), word);
+    }
+}
+
+#[test]
+fn test_write_file() {
+    use super::file::parse_file;
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("aseprite") {
+            continue;
+        }
+        let input = std::fs::read(&path).unwrap();
+        let file = parse_file(&input).unwrap();
+        let output = write_file(&file).unwrap();
+        let written = parse_file(&output).unwrap();
+        let header = Header {
+            file_size: file.header.file_size,
+            ..written.header
+        };
+        assert_eq!(header, file.header, "{:?}", path);
+        assert_eq!(written.header.file_size as usize, output.len());
+        assert_eq!(
+            format!("{:?}", written.frames),
+            format!("{:?}", file.frames),
+            "{:?}",
+            path
+        );
+        for (written, file) in [
+            (
+                format!("{:?}", written.palette),
+                format!("{:?}", file.palette),
+            ),
+            (
+                format!("{:?}", written.palettes),
+                format!("{:?}", file.palettes),
+            ),
+            (
+                format!("{:?}", written.layers),
+                format!("{:?}", file.layers),
+            ),
+            (format!("{:?}", written.tags), format!("{:?}", file.tags)),
+            (
+                format!("{:?}", written.slices),
+                format!("{:?}", file.slices),
+            ),
+            (
+                format!("{:?}", written.tilesets),
+                format!("{:?}", file.tilesets),
+            ),
+            (
+                format!("{:?}", written.user_data),
+                format!("{:?}", file.user_data),
+            ),
+        ] {
+            assert_eq!(written, file, "{:?}", path);
+        }
+        // Writing the file again results in the same output
+        assert_eq!(write_file(&written).unwrap(), output);
+    }
+}
+
+#[test]
+fn test_write_palette_names() {
+    use super::file::parse_file;
+    let data = std::fs::read("./tests/indexed_background.aseprite").unwrap();
+    let mut file = parse_file(&data).unwrap();
+    let palette = file.palette.as_mut().unwrap();
+    let _ = palette.names.insert(1, "Red".to_owned());
+    file.palettes[0].1 = palette.clone();
+    let written = write_file(&file).unwrap();
+    let written = parse_file(&written).unwrap();
+    let names = &written.palette.as_ref().unwrap().names;
+    assert_eq!(names.get(&1).map(String::as_str), Some("Red"));
+    assert_eq!(names.len(), 1);
+}
@@ -0,0 +1,693 @@
+//! This module contains a builder for creating new sprites from scratch.
+//!
+//! The [`SpriteBuilder`] owns all of its data and uses the writer of the
+//! `binary`-module to produce the contents of an `.aseprite` file:
+//!
+//! ```rust
+//! use aseprite_loader::{
+//!     binary::{chunks::tags::AnimationDirection, color_depth::ColorDepth},
+//!     builder::{Cel, LayerOptions, SpriteBuilder},
+//! };
+//!
+//! let mut sprite = SpriteBuilder::new(2, 2, ColorDepth::Rgba);
+//! let layer = sprite.add_layer("Layer 1", &LayerOptions::default())?;
+//! for duration in [100, 200] {
+//!     let frame = sprite.add_frame(duration);
+//!     sprite.add_cel(frame, layer, Cel::new(1, 1, vec![255, 0, 0, 255]))?;
+//! }
+//! sprite.add_tag("Idle", 0..=1, AnimationDirection::Forward, 0)?;
+//! let data = sprite.build()?;
+//! # Ok::<(), aseprite_loader::builder::BuildError>(())
+//! ```
+
+use std::{borrow::Cow, io::Write, ops::RangeInclusive};
+
+use flate2::{write::ZlibEncoder, Compression};
+use thiserror::Error;
+
+use crate::binary::{
+    blend_mode::BlendMode,
+    chunks::{
+        cel::{CelChunk, CelContent},
+        layer::{LayerChunk, LayerFlags, LayerType},
+        slice::{SliceChunk, SliceFlags, SliceKey},
+        tags::{AnimationDirection, Tag},
+    },
+    color_depth::ColorDepth,
+    errors::WriteError,
+    file::File,
+    frame::Frame,
+    header::Header,
+    image::Image,
+    palette::Palette,
+    scalars::Color,
+    write::write_file,
+};
+
+/// Builder for creating new sprites
+#[derive(Debug, Clone)]
+pub struct SpriteBuilder {
+    width: u16,
+    height: u16,
+    color_depth: ColorDepth,
+    transparent_index: u8,
+    palette: Vec<Color>,
+    layers: Vec<BuilderLayer>,
+    frames: Vec<BuilderFrame>,
+    tags: Vec<BuilderTag>,
+    slices: Vec<BuilderSlice>,
+}
+
+/// Options for [`SpriteBuilder::add_layer`] and [`SpriteBuilder::add_group`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerOptions {
+    /// The group this layer is part of
+    pub parent: Option<usize>,
+    /// Blend mode of the layer
+    pub blend_mode: BlendMode,
+    /// Opacity of the layer
+    pub opacity: u8,
+    /// Visibility of the layer
+    pub visible: bool,
+}
+
+impl Default for LayerOptions {
+    fn default() -> Self {
+        Self {
+            parent: None,
+            blend_mode: BlendMode::Normal,
+            opacity: 255,
+            visible: true,
+        }
+    }
+}
+
+/// A cel which can be added via [`SpriteBuilder::add_cel`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cel {
+    /// X position of the cel
+    pub x: i16,
+    /// Y position of the cel
+    pub y: i16,
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// Opacity of the cel
+    pub opacity: u8,
+    /// Z-index of the cel
+    pub z_index: i16,
+    /// Pixels in the color depth of the sprite: RGBA (4 bytes per pixel),
+    /// grayscale (value and alpha, 2 bytes per pixel) or indexed (1 byte
+    /// per pixel).
+    pub pixels: Vec<u8>,
+}
+
+impl Cel {
+    /// Create a fully opaque cel at the origin of the sprite
+    pub fn new(width: u16, height: u16, pixels: Vec<u8>) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            opacity: 255,
+            z_index: 0,
+            pixels,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BuilderLayer {
+    name: String,
+    group: bool,
+    options: LayerOptions,
+}
+
+#[derive(Debug, Clone)]
+struct BuilderFrame {
+    duration: u16,
+    cels: Vec<(usize, Cel)>,
+}
+
+#[derive(Debug, Clone)]
+struct BuilderTag {
+    name: String,
+    frames: RangeInclusive<u16>,
+    direction: AnimationDirection,
+    repeat: u16,
+}
+
+#[derive(Debug, Clone)]
+struct BuilderSlice {
+    name: String,
+    keys: Vec<SliceKey>,
+}
+
+impl SpriteBuilder {
+    /// Create a new sprite without any layers and frames
+    pub fn new(width: u16, height: u16, color_depth: ColorDepth) -> Self {
+        Self {
+            width,
+            height,
+            color_depth,
+            transparent_index: 0,
+            palette: Vec::new(),
+            layers: Vec::new(),
+            frames: Vec::new(),
+            tags: Vec::new(),
+            slices: Vec::new(),
+        }
+    }
+    /// Set the palette of the sprite. Indexed sprites require a palette.
+    pub fn set_palette(&mut self, colors: &[Color]) -> Result<(), BuildError> {
+        if colors.len() > 256 {
+            return Err(BuildError::PaletteTooLarge(colors.len()));
+        }
+        self.palette = colors.to_vec();
+        Ok(())
+    }
+    /// Set the palette entry which represents the transparent color in
+    /// indexed sprites
+    pub fn set_transparent_index(&mut self, index: u8) {
+        self.transparent_index = index;
+    }
+    /// Add a layer on top of all other layers of its parent. Returns the
+    /// layer index which is used when adding cels.
+    pub fn add_layer(
+        &mut self,
+        name: impl Into<String>,
+        options: &LayerOptions,
+    ) -> Result<usize, BuildError> {
+        self.push_layer(name.into(), false, options)
+    }
+    /// Add a layer group on top of all other layers of its parent. Returns
+    /// the layer index which can be used as parent for other layers.
+    pub fn add_group(
+        &mut self,
+        name: impl Into<String>,
+        options: &LayerOptions,
+    ) -> Result<usize, BuildError> {
+        self.push_layer(name.into(), true, options)
+    }
+    fn push_layer(
+        &mut self,
+        name: String,
+        group: bool,
+        options: &LayerOptions,
+    ) -> Result<usize, BuildError> {
+        if let Some(parent) = options.parent {
+            let layer = self
+                .layers
+                .get(parent)
+                .ok_or(BuildError::LayerIndexOutOfBounds(parent))?;
+            if !layer.group {
+                return Err(BuildError::ParentNotAGroup(parent));
+            }
+        }
+        if self.layers.len() >= usize::from(u16::MAX) {
+            return Err(BuildError::TooManyLayers);
+        }
+        self.layers.push(BuilderLayer {
+            name,
+            group,
+            options: *options,
+        });
+        Ok(self.layers.len() - 1)
+    }
+    /// Add a frame with the given duration in milliseconds. Returns the
+    /// frame index which is used when adding cels.
+    pub fn add_frame(&mut self, duration: u16) -> usize {
+        self.frames.push(BuilderFrame {
+            duration,
+            cels: Vec::new(),
+        });
+        self.frames.len() - 1
+    }
+    /// Add a cel to the given frame and layer. An existing cel of the same
+    /// frame and layer is replaced.
+    pub fn add_cel(&mut self, frame: usize, layer: usize, cel: Cel) -> Result<(), BuildError> {
+        let builder_layer = self
+            .layers
+            .get(layer)
+            .ok_or(BuildError::LayerIndexOutOfBounds(layer))?;
+        if builder_layer.group {
+            return Err(BuildError::CelInGroup(layer));
+        }
+        let pixel_size = self
+            .color_depth
+            .pixel_size()
+            .ok_or(BuildError::UnsupportedColorDepth)?;
+        let expected = usize::from(cel.width) * usize::from(cel.height) * pixel_size;
+        if cel.pixels.len() != expected {
+            return Err(BuildError::InvalidPixelData {
+                expected,
+                actual: cel.pixels.len(),
+            });
+        }
+        let builder_frame = self
+            .frames
+            .get_mut(frame)
+            .ok_or(BuildError::FrameIndexOutOfBounds(frame))?;
+        builder_frame.cels.retain(|(index, _)| *index != layer);
+        builder_frame.cels.push((layer, cel));
+        Ok(())
+    }
+    /// Add an animation tag for the given frames. A `repeat` value of
+    /// zero repeats the animation infinitely.
+    pub fn add_tag(
+        &mut self,
+        name: impl Into<String>,
+        frames: RangeInclusive<usize>,
+        direction: AnimationDirection,
+        repeat: u16,
+    ) -> Result<(), BuildError> {
+        let (from, to) = (*frames.start(), *frames.end());
+        if from > to {
+            return Err(BuildError::InvalidTagRange(from, to));
+        }
+        if to >= self.frames.len() {
+            return Err(BuildError::FrameIndexOutOfBounds(to));
+        }
+        self.tags.push(BuilderTag {
+            name: name.into(),
+            // Frame indices always fit into a WORD as this is checked
+            // when building the sprite.
+            frames: (from as u16)..=(to as u16),
+            direction,
+            repeat,
+        });
+        Ok(())
+    }
+    /// Add a slice. Every key defines the bounds of the slice starting with
+    /// the frame of the key.
+    pub fn add_slice(
+        &mut self,
+        name: impl Into<String>,
+        keys: &[SliceKey],
+    ) -> Result<(), BuildError> {
+        for key in keys {
+            let frame = key.frame_number as usize;
+            if frame >= self.frames.len() {
+                return Err(BuildError::FrameIndexOutOfBounds(frame));
+            }
+        }
+        self.slices.push(BuilderSlice {
+            name: name.into(),
+            keys: keys.to_vec(),
+        });
+        Ok(())
+    }
+    /// Create the contents of an `.aseprite` file
+    pub fn build(&self) -> Result<Vec<u8>, BuildError> {
+        if self.frames.is_empty() {
+            return Err(BuildError::NoFrames);
+        }
+        if self.color_depth == ColorDepth::Indexed && self.palette.is_empty() {
+            return Err(BuildError::MissingPalette);
+        }
+        let frame_count =
+            u16::try_from(self.frames.len()).map_err(|_| BuildError::TooManyFrames)?;
+        // The layers are stored in the file in the order of a depth-first
+        // traversal of the layer tree. `layer_map` maps the builder layer
+        // indices to the file layer indices.
+        let mut order = Vec::with_capacity(self.layers.len());
+        self.layer_order(None, 0, &mut order);
+        let mut layer_map = vec![0; self.layers.len()];
+        for (file_index, (layer_index, _)) in order.iter().enumerate() {
+            layer_map[*layer_index] = file_index;
+        }
+        let layers = order
+            .iter()
+            .map(|&(layer_index, child_level)| {
+                let layer = &self.layers[layer_index];
+                let mut flags = LayerFlags::EDITABLE;
+                flags.set(LayerFlags::VISIBLE, layer.options.visible);
+                LayerChunk {
+                    flags,
+                    layer_type: if layer.group {
+                        LayerType::Group
+                    } else {
+                        LayerType::Normal
+                    },
+                    child_level,
+                    blend_mode: layer.options.blend_mode,
+                    opacity: layer.options.opacity,
+                    name: Cow::Borrowed(&layer.name),
+                    tileset_index: None,
+                    user_data: None,
+                }
+            })
+            .collect();
+        let compressed = self
+            .frames
+            .iter()
+            .map(|frame| {
+                frame
+                    .cels
+                    .iter()
+                    .map(|(_, cel)| compress(&cel.pixels))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let frames = self
+            .frames
+            .iter()
+            .zip(&compressed)
+            .map(|(frame, data)| {
+                let mut cels: Vec<Option<CelChunk<'_>>> =
+                    (0..self.layers.len()).map(|_| None).collect();
+                for ((layer_index, cel), data) in frame.cels.iter().zip(data) {
+                    let layer_index = layer_map[*layer_index];
+                    cels[layer_index] = Some(CelChunk {
+                        // The number of layers is checked when adding layers
+                        layer_index: layer_index as u16,
+                        x: cel.x,
+                        y: cel.y,
+                        opacity: cel.opacity,
+                        z_index: cel.z_index,
+                        content: CelContent::Image(Image {
+                            width: cel.width,
+                            height: cel.height,
+                            data: Cow::Borrowed(data),
+                            compressed: true,
+                        }),
+                        user_data: None,
+                    });
+                }
+                Frame {
+                    duration: frame.duration,
+                    cels,
+                }
+            })
+            .collect();
+        #[allow(deprecated)]
+        let tags = self
+            .tags
+            .iter()
+            .map(|tag| Tag {
+                frames: tag.frames.clone(),
+                animation_direction: tag.direction,
+                animation_repeat: tag.repeat,
+                color: [0, 0, 0],
+                name: Cow::Borrowed(&tag.name),
+                user_data: None,
+            })
+            .collect();
+        let slices = self
+            .slices
+            .iter()
+            .map(|slice| {
+                let mut flags = SliceFlags::empty();
+                for key in &slice.keys {
+                    if key.nine_patch.is_some() {
+                        flags |= SliceFlags::NINE_PATCH;
+                    }
+                    if key.pivot.is_some() {
+                        flags |= SliceFlags::PIVOT;
+                    }
+                }
+                SliceChunk {
+                    name: Cow::Borrowed(&slice.name),
+                    flags,
+                    slice_keys: slice.keys.clone(),
+                    user_data: None,
+                }
+            })
+            .collect();
+        let mut palette = Palette::default();
+        palette.colors[..self.palette.len()].copy_from_slice(&self.palette);
+        palette.color_count = self.palette.len();
+        #[allow(deprecated)]
+        let file = File {
+            header: Header {
+                // The file size is calculated by the writer
+                file_size: 0,
+                frames: frame_count,
+                width: self.width,
+                height: self.height,
+                color_depth: self.color_depth,
+                // Layer opacity and the opacity and blend mode of
+                // groups are valid
+                flags: 1 | 2,
+                speed: 100,
+                transparent_index: self.transparent_index,
+                color_count: self.palette.len().max(1) as u16,
+                pixel_width: 1,
+                pixel_height: 1,
+                grid_x: 0,
+                grid_y: 0,
+                grid_width: 16,
+                grid_height: 16,
+            },
+            palette: Some(palette),
+            palettes: Vec::new(),
+            layers,
+            frames,
+            tags,
+            slices,
+            tilesets: Vec::new(),
+            user_data: None,
+            diagnostics: Vec::new(),
+        };
+        Ok(write_file(&file)?)
+    }
+    /// Collect the children of `parent` depth-first as pairs of layer
+    /// index and child level.
+    fn layer_order(&self, parent: Option<usize>, child_level: u16, order: &mut Vec<(usize, u16)>) {
+        for (index, layer) in self.layers.iter().enumerate() {
+            if layer.options.parent == parent {
+                order.push((index, child_level));
+                if layer.group {
+                    self.layer_order(Some(index), child_level + 1, order);
+                }
+            }
+        }
+    }
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing into a `Vec` never fails
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Errors that can occur while building a sprite
+#[allow(missing_copy_implementations)]
+#[derive(Error, Debug)]
+pub enum BuildError {
+    /// The sprite does not contain any frames.
+    #[error("sprite contains no frames")]
+    NoFrames,
+    /// The sprite contains more frames than the file format supports.
+    #[error("too many frames")]
+    TooManyFrames,
+    /// The sprite contains more layers than the file format supports.
+    #[error("too many layers")]
+    TooManyLayers,
+    /// A layer index was outside available layer bounds.
+    #[error("layer index out of range: {0}")]
+    LayerIndexOutOfBounds(usize),
+    /// A frame index was outside available frame bounds.
+    #[error("frame index out of range: {0}")]
+    FrameIndexOutOfBounds(usize),
+    /// The parent of a layer is not a layer group.
+    #[error("parent layer is not a group: {0}")]
+    ParentNotAGroup(usize),
+    /// Layer groups can't contain cels.
+    #[error("layer is a group: {0}")]
+    CelInGroup(usize),
+    /// The first frame of a tag is after the last frame.
+    #[error("invalid tag range: {0}..={1}")]
+    InvalidTagRange(usize, usize),
+    /// Palettes are limited to 256 colors.
+    #[error("palette too large: {0} colors")]
+    PaletteTooLarge(usize),
+    /// Indexed sprites require a palette.
+    #[error("missing palette")]
+    MissingPalette,
+    /// The sprite uses an unsupported color depth.
+    #[error("unsupported color depth")]
+    UnsupportedColorDepth,
+    /// The length of the pixel data does not match the cel size.
+    #[error("invalid pixel data: expected {expected} bytes, got {actual}")]
+    InvalidPixelData {
+        /// Expected number of bytes
+        expected: usize,
+        /// Actual number of bytes
+        actual: usize,
+    },
+    /// Writing the file failed.
+    #[error("writing failed: {0}")]
+    Write(#[from] WriteError),
+}
+
+#[test]
+fn test_build() {
+    use crate::loader::{AsepriteFile, LayerSelection};
+    let mut sprite = SpriteBuilder::new(2, 1, ColorDepth::Rgba);
+    let group = sprite
+        .add_group(
+            "Group",
+            &LayerOptions {
+                opacity: 128,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let background = sprite
+        .add_layer("Background", &LayerOptions::default())
+        .unwrap();
+    let child = sprite
+        .add_layer(
+            "Child",
+            &LayerOptions {
+                parent: Some(group),
+                blend_mode: BlendMode::Multiply,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let frame = sprite.add_frame(50);
+    sprite
+        .add_cel(
+            frame,
+            background,
+            Cel::new(2, 1, vec![255, 255, 255, 255, 0, 0, 0, 0]),
+        )
+        .unwrap();
+    sprite
+        .add_cel(
+            frame,
+            child,
+            Cel {
+                x: 1,
+                ..Cel::new(1, 1, vec![255, 0, 0, 255])
+            },
+        )
+        .unwrap();
+    let _ = sprite.add_frame(100);
+    sprite
+        .add_tag("Tag", 0..=1, AnimationDirection::PingPong, 2)
+        .unwrap();
+    sprite
+        .add_slice(
+            "Slice",
+            &[SliceKey {
+                frame_number: 1,
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 1,
+                nine_patch: None,
+                pivot: None,
+            }],
+        )
+        .unwrap();
+    let data = sprite.build().unwrap();
+
+    let file = AsepriteFile::load(&data).unwrap();
+    assert_eq!(file.size(), (2, 1));
+    assert_eq!(file.file.header.flags, 1 | 2);
+    let names: Vec<_> = file.layers().iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["Group", "Child", "Background"]);
+    assert_eq!(file.layers()[1].parent, Some(0));
+    assert_eq!(file.layers()[1].blend_mode, BlendMode::Multiply);
+    assert_eq!(file.layers()[0].opacity, 128);
+    assert_eq!(file.frames()[0].duration, 50);
+    assert_eq!(file.frames()[1].duration, 100);
+    assert_eq!(file.tags()[0].name, "Tag");
+    assert_eq!(file.tags()[0].range, 0..=1);
+    assert_eq!(file.tags()[0].repeat, Some(2));
+    assert_eq!(file.slices()[0].name, "Slice");
+
+    let mut target = vec![0; 2 * 4];
+    file.render_frame(0, &mut target, &LayerSelection::All)
+        .unwrap();
+    // The group is drawn below the background layer which is fully
+    // opaque in the first pixel only.
+    assert_eq!(target, [255, 255, 255, 255, 255, 0, 0, 128]);
+}
+
+#[test]
+fn test_build_indexed() {
+    use crate::loader::{AsepriteFile, LayerSelection};
+    let mut sprite = SpriteBuilder::new(2, 1, ColorDepth::Indexed);
+    let colors = [
+        Color::default(),
+        Color {
+            red: 10,
+            green: 20,
+            blue: 30,
+            alpha: 255,
+        },
+    ];
+    assert!(matches!(sprite.build(), Err(BuildError::NoFrames)));
+    let layer = sprite.add_layer("Layer", &LayerOptions::default()).unwrap();
+    let frame = sprite.add_frame(100);
+    sprite
+        .add_cel(frame, layer, Cel::new(2, 1, vec![1, 0]))
+        .unwrap();
+    assert!(matches!(sprite.build(), Err(BuildError::MissingPalette)));
+    sprite.set_palette(&colors).unwrap();
+    let data = sprite.build().unwrap();
+
+    let file = AsepriteFile::load(&data).unwrap();
+    let mut target = vec![0; 2 * 4];
+    file.render_frame(0, &mut target, &LayerSelection::All)
+        .unwrap();
+    assert_eq!(target, [10, 20, 30, 255, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_build_errors() {
+    let mut sprite = SpriteBuilder::new(2, 2, ColorDepth::Rgba);
+    let layer = sprite.add_layer("Layer", &LayerOptions::default()).unwrap();
+    let group = sprite.add_group("Group", &LayerOptions::default()).unwrap();
+    let frame = sprite.add_frame(100);
+    assert!(matches!(
+        sprite.add_layer(
+            "Child",
+            &LayerOptions {
+                parent: Some(layer),
+                ..Default::default()
+            }
+        ),
+        Err(BuildError::ParentNotAGroup(0))
+    ));
+    assert!(matches!(
+        sprite.add_cel(frame, group, Cel::new(0, 0, vec![])),
+        Err(BuildError::CelInGroup(1))
+    ));
+    assert!(matches!(
+        sprite.add_cel(frame, 2, Cel::new(0, 0, vec![])),
+        Err(BuildError::LayerIndexOutOfBounds(2))
+    ));
+    assert!(matches!(
+        sprite.add_cel(1, layer, Cel::new(0, 0, vec![])),
+        Err(BuildError::FrameIndexOutOfBounds(1))
+    ));
+    assert!(matches!(
+        sprite.add_cel(frame, layer, Cel::new(1, 1, vec![0; 3])),
+        Err(BuildError::InvalidPixelData {
+            expected: 4,
+            actual: 3
+        })
+    ));
+    #[allow(clippy::reversed_empty_ranges)]
+    let range = 1..=0;
+    assert!(matches!(
+        sprite.add_tag("Tag", range, AnimationDirection::Forward, 0),
+        Err(BuildError::InvalidTagRange(1, 0))
+    ));
+    assert!(matches!(
+        sprite.add_tag("Tag", 0..=1, AnimationDirection::Forward, 0),
+        Err(BuildError::FrameIndexOutOfBounds(1))
+    ));
+    assert!(matches!(
+        sprite.set_palette(&[Color::default(); 257]),
+        Err(BuildError::PaletteTooLarge(257))
+    ));
+}
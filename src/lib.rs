@@ -22,4 +22,5 @@
 #![allow(clippy::uninlined_format_args)]
 
 pub mod binary;
+pub mod builder;
 pub mod loader;
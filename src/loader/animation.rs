@@ -0,0 +1,435 @@
+use std::time::Duration;
+
+use crate::binary::chunks::tags::AnimationDirection;
+
+use super::{Frame, LoadSpriteError, Tag};
+
+/// A single pass over the frames of a tag.
+#[derive(Debug, Clone)]
+struct Pass {
+    /// Frame indices and their durations in playback order.
+    steps: Vec<(usize, Duration)>,
+    /// Sum of all durations of this pass.
+    duration: Duration,
+}
+
+impl Pass {
+    fn new(frame_indices: impl Iterator<Item = usize>, frames: &[Frame]) -> Self {
+        let steps: Vec<_> = frame_indices
+            .map(|index| {
+                // Frames without a duration would make it impossible to
+                // advance the animation. They are treated as 1ms long.
+                let millis = frames[index].duration.max(1);
+                (index, Duration::from_millis(millis.into()))
+            })
+            .collect();
+        let duration = steps.iter().map(|(_, duration)| *duration).sum();
+        Self { steps, duration }
+    }
+    fn frame_at(&self, mut elapsed: Duration) -> usize {
+        for &(frame, duration) in &self.steps {
+            if elapsed < duration {
+                return frame;
+            }
+            elapsed -= duration;
+        }
+        self.last_frame()
+    }
+    fn last_frame(&self) -> usize {
+        self.steps
+            .last()
+            .map(|&(frame, _)| frame)
+            .unwrap_or_default()
+    }
+}
+
+/// The playback state of an [`Animation`] at a given point in time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Position {
+    /// Index into [`AsepriteFile::frames`](super::AsepriteFile::frames).
+    frame: usize,
+    /// Number of completed loops.
+    loops: u64,
+    /// Whether the last loop was completed.
+    finished: bool,
+}
+
+/// An animation created from a [`Tag`]
+///
+/// This is a stateless description of the playback order and timing of a
+/// tag. Use [`AnimationPlayer`] to keep track of the playback state.
+///
+/// Every pass over the frames of the tag counts as one loop. For ping-pong
+/// animations this means that going forward and going back are counted as
+/// two loops.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// The first pass of the animation.
+    first: Pass,
+    /// Passes following the first pass. These are repeated until the
+    /// animation is finished.
+    passes: Vec<Pass>,
+    /// Number of passes (`None` means infinite).
+    repeat: Option<u16>,
+}
+
+impl Animation {
+    /// Create an animation for the given tag. The `frames` are the frames
+    /// of the file the tag belongs to. A repeat count of zero is treated
+    /// as infinite like in the file format.
+    pub fn from_tag(tag: &Tag, frames: &[Frame]) -> Result<Self, LoadSpriteError> {
+        let from = usize::from(*tag.range.start());
+        let to = usize::from(*tag.range.end());
+        if from > to {
+            return Err(LoadSpriteError::InvalidTagRange(tag.name.clone()));
+        }
+        if to >= frames.len() {
+            return Err(LoadSpriteError::FrameIndexOutOfRange(to));
+        }
+        let forward = Pass::new(from..=to, frames);
+        let reverse = Pass::new((from..=to).rev(), frames);
+        let (first, passes) = match tag.direction {
+            AnimationDirection::Reverse => (reverse.clone(), vec![reverse]),
+            // The frame at which the direction changes is not repeated
+            // unless the tag consists of a single frame.
+            AnimationDirection::PingPong if from < to => (
+                forward,
+                vec![
+                    Pass::new((from..to).rev(), frames),
+                    Pass::new(from + 1..=to, frames),
+                ],
+            ),
+            AnimationDirection::PingPongReverse if from < to => (
+                reverse,
+                vec![
+                    Pass::new(from + 1..=to, frames),
+                    Pass::new((from..to).rev(), frames),
+                ],
+            ),
+            _ => (forward.clone(), vec![forward]),
+        };
+        Ok(Self {
+            first,
+            passes,
+            repeat: tag.repeat.filter(|&repeat| repeat > 0),
+        })
+    }
+
+    /// Get the number of loops (`None` means infinite).
+    pub fn repeat(&self) -> Option<u16> {
+        self.repeat
+    }
+
+    /// Get the total duration of the animation or `None` if the
+    /// animation loops infinitely.
+    pub fn duration(&self) -> Option<Duration> {
+        let repeat = u32::from(self.repeat?);
+        let mut duration = self.first.duration;
+        for n in 1..repeat {
+            duration += self.pass(n).duration;
+        }
+        Some(duration)
+    }
+
+    /// Get the frame index (into [`AsepriteFile::frames`](super::AsepriteFile::frames))
+    /// which is shown after `elapsed` time. Finished animations stay at
+    /// their last frame.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        self.position(elapsed).frame
+    }
+
+    /// Get the number of loops completed after `elapsed` time.
+    pub fn loops_at(&self, elapsed: Duration) -> u64 {
+        self.position(elapsed).loops
+    }
+
+    /// Check if the animation is finished after `elapsed` time. This is
+    /// never the case for animations without a repeat count.
+    pub fn is_finished_at(&self, elapsed: Duration) -> bool {
+        self.position(elapsed).finished
+    }
+
+    /// Get the pass with the given number.
+    fn pass(&self, n: u32) -> &Pass {
+        match n {
+            0 => &self.first,
+            n => &self.passes[(n as usize - 1) % self.passes.len()],
+        }
+    }
+
+    fn position(&self, elapsed: Duration) -> Position {
+        if elapsed < self.first.duration {
+            return Position {
+                frame: self.first.frame_at(elapsed),
+                loops: 0,
+                finished: false,
+            };
+        }
+        let elapsed = (elapsed - self.first.duration).as_nanos();
+        let cycle: u128 = self.passes.iter().map(|p| p.duration.as_nanos()).sum();
+        let cycles = elapsed / cycle;
+        let mut rest = elapsed % cycle;
+        // Number of passes completed before the current one
+        let mut loops = 1 + cycles.saturating_mul(self.passes.len() as u128);
+        let mut current = &self.passes[0];
+        for pass in &self.passes {
+            current = pass;
+            if rest < pass.duration.as_nanos() {
+                break;
+            }
+            rest -= pass.duration.as_nanos();
+            loops += 1;
+        }
+        match self.repeat {
+            Some(repeat) if loops >= u128::from(repeat) => Position {
+                frame: self.pass(u32::from(repeat) - 1).last_frame(),
+                loops: repeat.into(),
+                finished: true,
+            },
+            _ => Position {
+                // The remaining time is always less than the duration of
+                // the current pass.
+                frame: current.frame_at(Duration::from_nanos(rest as u64)),
+                loops: u64::try_from(loops).unwrap_or(u64::MAX),
+                finished: false,
+            },
+        }
+    }
+}
+
+/// Events which occurred during a call to [`AnimationPlayer::advance`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct AnimationEvents {
+    /// Number of loops which were completed.
+    pub loops_completed: u64,
+    /// The animation finished playing.
+    pub finished: bool,
+}
+
+/// Playback state of an [`Animation`]
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    animation: Animation,
+    elapsed: Duration,
+    position: Position,
+}
+
+impl AnimationPlayer {
+    /// Create a new player starting at the beginning of the animation.
+    pub fn new(animation: Animation) -> Self {
+        let position = animation.position(Duration::ZERO);
+        Self {
+            animation,
+            elapsed: Duration::ZERO,
+            position,
+        }
+    }
+
+    /// Get the animation played by this player.
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    /// Get the time elapsed since the start of the animation.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Get the frame index (into [`AsepriteFile::frames`](super::AsepriteFile::frames))
+    /// of the current frame.
+    pub fn frame(&self) -> usize {
+        self.position.frame
+    }
+
+    /// Get the number of completed loops.
+    pub fn loops(&self) -> u64 {
+        self.position.loops
+    }
+
+    /// Check if the animation is finished.
+    pub fn is_finished(&self) -> bool {
+        self.position.finished
+    }
+
+    /// Advance the animation by `delta` and return the events which
+    /// occurred while doing so.
+    pub fn advance(&mut self, delta: Duration) -> AnimationEvents {
+        let old = self.position;
+        self.elapsed = self.elapsed.saturating_add(delta);
+        self.position = self.animation.position(self.elapsed);
+        AnimationEvents {
+            loops_completed: self.position.loops.saturating_sub(old.loops),
+            finished: self.position.finished && !old.finished,
+        }
+    }
+
+    /// Jump to the given point in time. This does not report any events.
+    pub fn seek(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+        self.position = self.animation.position(elapsed);
+    }
+
+    /// Restart the animation from the beginning.
+    pub fn reset(&mut self) {
+        self.seek(Duration::ZERO);
+    }
+}
+
+#[cfg(test)]
+fn test_frames(durations: &[u16]) -> Vec<Frame> {
+    durations
+        .iter()
+        .map(|&duration| Frame {
+            duration,
+            origin: (0, 0),
+            cels: Vec::new(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn test_tag(
+    range: std::ops::RangeInclusive<u16>,
+    direction: AnimationDirection,
+    repeat: Option<u16>,
+) -> Tag {
+    Tag {
+        name: "Tag".to_owned(),
+        range,
+        direction,
+        repeat,
+    }
+}
+
+#[cfg(test)]
+fn frame_sequence(animation: &Animation, count: u64) -> Vec<usize> {
+    (0..count)
+        .map(|n| animation.frame_at(Duration::from_millis(n * 100)))
+        .collect()
+}
+
+#[test]
+fn test_animation_directions() {
+    let frames = test_frames(&[100; 5]);
+    let sequence = |direction| {
+        let tag = test_tag(1..=3, direction, None);
+        frame_sequence(&Animation::from_tag(&tag, &frames).unwrap(), 9)
+    };
+    assert_eq!(
+        sequence(AnimationDirection::Forward),
+        [1, 2, 3, 1, 2, 3, 1, 2, 3]
+    );
+    assert_eq!(
+        sequence(AnimationDirection::Reverse),
+        [3, 2, 1, 3, 2, 1, 3, 2, 1]
+    );
+    assert_eq!(
+        sequence(AnimationDirection::PingPong),
+        [1, 2, 3, 2, 1, 2, 3, 2, 1]
+    );
+    assert_eq!(
+        sequence(AnimationDirection::PingPongReverse),
+        [3, 2, 1, 2, 3, 2, 1, 2, 3]
+    );
+}
+
+#[test]
+fn test_animation_durations() {
+    let frames = test_frames(&[100, 50, 0]);
+    let tag = test_tag(0..=2, AnimationDirection::Forward, None);
+    let animation = Animation::from_tag(&tag, &frames).unwrap();
+    assert_eq!(animation.duration(), None);
+    assert_eq!(animation.frame_at(Duration::from_millis(99)), 0);
+    assert_eq!(animation.frame_at(Duration::from_millis(100)), 1);
+    assert_eq!(animation.frame_at(Duration::from_millis(149)), 1);
+    assert_eq!(animation.frame_at(Duration::from_millis(150)), 2);
+    assert_eq!(animation.frame_at(Duration::from_millis(151)), 0);
+    assert_eq!(animation.loops_at(Duration::from_millis(151)), 1);
+}
+
+#[test]
+fn test_animation_repeat() {
+    let frames = test_frames(&[100; 3]);
+    let tag = test_tag(0..=2, AnimationDirection::PingPong, Some(3));
+    let animation = Animation::from_tag(&tag, &frames).unwrap();
+    assert_eq!(animation.duration(), Some(Duration::from_millis(700)));
+    assert_eq!(frame_sequence(&animation, 9), [0, 1, 2, 1, 0, 1, 2, 2, 2]);
+    assert!(!animation.is_finished_at(Duration::from_millis(699)));
+    assert!(animation.is_finished_at(Duration::from_millis(700)));
+    assert_eq!(animation.loops_at(Duration::from_secs(60)), 3);
+}
+
+#[test]
+fn test_animation_player() {
+    let frames = test_frames(&[100; 4]);
+    let tag = test_tag(0..=1, AnimationDirection::Forward, Some(3));
+    let mut player = AnimationPlayer::new(Animation::from_tag(&tag, &frames).unwrap());
+    assert_eq!(player.frame(), 0);
+
+    let events = player.advance(Duration::from_millis(150));
+    assert_eq!(player.frame(), 1);
+    assert_eq!(events, AnimationEvents::default());
+
+    let events = player.advance(Duration::from_millis(300));
+    assert_eq!(player.frame(), 0);
+    assert_eq!(player.loops(), 2);
+    assert_eq!(
+        events,
+        AnimationEvents {
+            loops_completed: 2,
+            finished: false
+        }
+    );
+
+    let events = player.advance(Duration::from_millis(1000));
+    assert_eq!(player.frame(), 1);
+    assert!(player.is_finished());
+    assert_eq!(
+        events,
+        AnimationEvents {
+            loops_completed: 1,
+            finished: true
+        }
+    );
+    assert_eq!(
+        player.advance(Duration::from_millis(100)),
+        Default::default()
+    );
+
+    player.seek(Duration::from_millis(250));
+    assert_eq!(player.frame(), 0);
+    assert_eq!(player.loops(), 1);
+    assert!(!player.is_finished());
+
+    player.reset();
+    assert_eq!(player.elapsed(), Duration::ZERO);
+    assert_eq!(player.loops(), 0);
+}
+
+#[test]
+fn test_animation_out_of_range() {
+    let frames = test_frames(&[100; 2]);
+    let tag = test_tag(0..=2, AnimationDirection::Forward, None);
+    assert!(matches!(
+        Animation::from_tag(&tag, &frames),
+        Err(LoadSpriteError::FrameIndexOutOfRange(2))
+    ));
+}
+
+#[test]
+fn test_animation_invalid_tag() {
+    let frames = test_frames(&[100; 3]);
+    let range = std::ops::RangeInclusive::new(2, 0);
+    let tag = test_tag(range, AnimationDirection::Forward, None);
+    assert!(matches!(
+        Animation::from_tag(&tag, &frames),
+        Err(LoadSpriteError::InvalidTagRange(name)) if name == "Tag"
+    ));
+    // A repeat count of zero loops infinitely
+    let tag = test_tag(0..=2, AnimationDirection::Forward, Some(0));
+    let animation = Animation::from_tag(&tag, &frames).unwrap();
+    assert_eq!(animation.repeat(), None);
+    assert_eq!(animation.duration(), None);
+    assert_eq!(animation.frame_at(Duration::from_millis(350)), 0);
+    assert!(!animation.is_finished_at(Duration::from_secs(60)));
+}
@@ -1,5 +1,7 @@
 use crate::binary::blend_mode::BlendMode;
 
+use super::LoadImageError;
+
 // Rust port of Aseprite's blend functions
 // https://github.com/aseprite/aseprite/blob/master/src/doc/blend_funcs.cpp
 // original implementation: https://github.com/alpine-alpaca/asefile/blob/main/src/blend.rs
@@ -26,16 +28,16 @@ impl Color {
         self.a.into()
     }
     fn r_f64(&self) -> f64 {
-        self.r.into()
+        f64::from(self.r) / 255.0
     }
     fn g_f64(&self) -> f64 {
-        self.g.into()
+        f64::from(self.g) / 255.0
     }
     fn b_f64(&self) -> f64 {
-        self.b.into()
+        f64::from(self.b) / 255.0
     }
     fn a_f64(&self) -> f64 {
-        self.a.into()
+        f64::from(self.a) / 255.0
     }
 }
 
@@ -86,14 +88,16 @@ impl From<&[i32; 4]> for Color {
 }
 
 impl From<&[f64]> for Color {
+    /// Convert channels in the range `0.0..=1.0`. Rounding errors of the
+    /// HSL blend modes can leave the range slightly so the result is clamped.
     fn from(value: &[f64]) -> Self {
         debug_assert!(value.len() == 4);
         Self::from(
             [
-                (value[0] * 255.0) as i32,
-                (value[1] * 255.0) as i32,
-                (value[2] * 255.0) as i32,
-                (value[3] * 255.0) as i32,
+                ((value[0] * 255.0) as i32).clamp(0, 255),
+                ((value[1] * 255.0) as i32).clamp(0, 255),
+                ((value[2] * 255.0) as i32).clamp(0, 255),
+                ((value[3] * 255.0) as i32).clamp(0, 255),
             ]
             .as_slice(),
         )
@@ -105,9 +109,216 @@ impl From<&[f64; 4]> for Color {
     }
 }
 
+/// A rectangle of pixels `x0..x1` and `y0..y1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bounds {
+    pub(crate) x0: i32,
+    pub(crate) y0: i32,
+    pub(crate) x1: i32,
+    pub(crate) y1: i32,
+}
+
+impl Bounds {
+    /// The bounds of an image with the given size
+    pub(crate) fn from_size(size: (u16, u16)) -> Self {
+        Self {
+            x0: 0,
+            y0: 0,
+            x1: size.0.into(),
+            y1: size.1.into(),
+        }
+    }
+    pub(crate) fn width(&self) -> usize {
+        (self.x1 - self.x0).max(0) as usize
+    }
+    pub(crate) fn height(&self) -> usize {
+        (self.y1 - self.y0).max(0) as usize
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+    pub(crate) fn intersect(&self, other: &Self) -> Self {
+        Self {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+}
+
+/// A RGBA8 image which is blended into
+///
+/// The pixel `(x, y)` is stored at byte `(y + position.1) * stride +
+/// (x + position.0) * 4` of `pixels`. Only pixels inside `bounds` are
+/// written, which allows blending into a region of a larger buffer.
+#[derive(Debug)]
+pub(crate) struct TargetImage<'t> {
+    pub(crate) pixels: &'t mut [u8],
+    /// Distance between the rows in bytes
+    pub(crate) stride: usize,
+    pub(crate) position: (isize, isize),
+    pub(crate) bounds: Bounds,
+}
+
+impl<'t> TargetImage<'t> {
+    /// A tightly packed image covering `bounds`
+    pub(crate) fn new(pixels: &'t mut [u8], bounds: Bounds) -> Self {
+        Self {
+            pixels,
+            stride: bounds.width() * 4,
+            position: (-bounds.x0 as isize, -bounds.y0 as isize),
+            bounds,
+        }
+    }
+    /// Byte offset of the given pixel which must be inside the bounds
+    fn offset(&self, x: i32, y: i32) -> usize {
+        (y as isize + self.position.1) as usize * self.stride
+            + (x as isize + self.position.0) as usize * 4
+    }
+    /// Make all pixels inside the bounds transparent
+    pub(crate) fn clear(&mut self) -> Result<(), LoadImageError> {
+        self.check_size()?;
+        let row_size = self.bounds.width() * 4;
+        for y in self.bounds.y0..self.bounds.y1 {
+            let start = self.offset(self.bounds.x0, y);
+            self.pixels[start..start + row_size].fill(0);
+        }
+        Ok(())
+    }
+    /// Check that the pixels inside the bounds fit into the buffer
+    fn check_size(&self) -> Result<(), LoadImageError> {
+        if self.bounds.is_empty() {
+            return Ok(());
+        }
+        let end = self.offset(self.bounds.x1 - 1, self.bounds.y1 - 1) + 4;
+        if self.pixels.len() < end {
+            return Err(LoadImageError::TargetBufferTooSmall);
+        }
+        Ok(())
+    }
+}
+
+/// Blend a RGBA8 `source` image located at `origin` into the RGBA8 `target`
+/// image. Pixels outside the bounds of `target` are skipped.
+///
+/// The source rectangle is clipped against the target once and the image
+/// is blended row by row. `BlendMode::Normal` has its own code paths which
+/// avoid the per-pixel function call and copy opaque pixels directly.
+pub(crate) fn blend_image(
+    target: &mut TargetImage<'_>,
+    source: &[u8],
+    source_size: (u16, u16),
+    origin: (i32, i32),
+    blend_mode: BlendMode,
+    opacity: u8,
+) -> Result<(), LoadImageError> {
+    let blend_fn = blend_mode_to_blend_fn(blend_mode)
+        .ok_or(LoadImageError::UnsupportedBlendMode(blend_mode))?;
+    target.check_size()?;
+    if source.len() < usize::from(source_size.0) * usize::from(source_size.1) * 4 {
+        return Err(LoadImageError::InvalidImageData);
+    }
+    let Some(clip) = Clip::new(target, source_size, origin) else {
+        return Ok(());
+    };
+    let target = &mut *target.pixels;
+    match blend_mode {
+        BlendMode::Normal if opacity == 255 => clip.for_each_row(target, source, normal_row),
+        BlendMode::Normal => clip.for_each_row(target, source, |target, source| {
+            blend_row(target, source, opacity, normal)
+        }),
+        _ => clip.for_each_row(target, source, |target, source| {
+            blend_row(target, source, opacity, blend_fn)
+        }),
+    }
+    Ok(())
+}
+
+/// The part of a source image which is inside the target image
+#[derive(Debug, Clone, Copy)]
+struct Clip {
+    target_stride: usize,
+    source_width: usize,
+    /// Offset of the first pixel in the target
+    target_start: usize,
+    /// First column and row in the source
+    source_x: usize,
+    source_y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Clip {
+    fn new(target: &TargetImage<'_>, source_size: (u16, u16), origin: (i32, i32)) -> Option<Self> {
+        let source = Bounds {
+            x0: origin.0,
+            y0: origin.1,
+            x1: origin.0.saturating_add(source_size.0.into()),
+            y1: origin.1.saturating_add(source_size.1.into()),
+        };
+        let clip = source.intersect(&target.bounds);
+        if clip.is_empty() {
+            return None;
+        }
+        Some(Self {
+            target_stride: target.stride,
+            source_width: source_size.0.into(),
+            target_start: target.offset(clip.x0, clip.y0),
+            source_x: (clip.x0 - origin.0) as usize,
+            source_y: (clip.y0 - origin.1) as usize,
+            width: clip.width(),
+            height: clip.height(),
+        })
+    }
+    /// Call `f` with the matching rows of the target and the source
+    fn for_each_row(&self, target: &mut [u8], source: &[u8], mut f: impl FnMut(&mut [u8], &[u8])) {
+        let len = self.width * 4;
+        for row in 0..self.height {
+            let target_start = self.target_start + row * self.target_stride;
+            let source_start = ((self.source_y + row) * self.source_width + self.source_x) * 4;
+            f(
+                &mut target[target_start..target_start + len],
+                &source[source_start..source_start + len],
+            );
+        }
+    }
+}
+
+/// Blend a row of pixels using the given blend function
+fn blend_row(
+    target: &mut [u8],
+    source: &[u8],
+    opacity: u8,
+    blend_fn: impl Fn(Color, Color, u8) -> Color,
+) {
+    for (target, source) in target.chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+        let out = blend_fn(Color::from(&*target), Color::from(source), opacity);
+        target.copy_from_slice(&[out.r, out.g, out.b, out.a]);
+    }
+}
+
+/// Blend a row of pixels using [`normal`] with full opacity. The result is
+/// the source pixel if it is opaque or the target pixel is transparent.
+/// Rows without translucent pixels are copied as a whole.
+fn normal_row(target: &mut [u8], source: &[u8]) {
+    if source.chunks_exact(4).all(|pixel| pixel[3] == 255) {
+        target.copy_from_slice(source);
+        return;
+    }
+    for (target, source) in target.chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+        if source[3] == 255 || target[3] == 0 {
+            target.copy_from_slice(source);
+        } else if source[3] != 0 {
+            let out = normal(Color::from(&*target), Color::from(source), 255);
+            target.copy_from_slice(&[out.r, out.g, out.b, out.a]);
+        }
+    }
+}
+
 type BlendFn = fn(Color, Color, u8) -> Color;
-pub(crate) fn blend_mode_to_blend_fn(mode: BlendMode) -> BlendFn {
-    match mode {
+pub(crate) fn blend_mode_to_blend_fn(mode: BlendMode) -> Option<BlendFn> {
+    Some(match mode {
         BlendMode::Normal => normal,
         BlendMode::Multiply => multiply,
         BlendMode::Screen => screen,
@@ -127,8 +338,8 @@ pub(crate) fn blend_mode_to_blend_fn(mode: BlendMode) -> BlendFn {
         BlendMode::Addition => addition,
         BlendMode::Subtract => subtract,
         BlendMode::Divide => divide,
-        m => panic!("unimplemented blend mode: {:?}", m),
-    }
+        BlendMode::Unknown(_) => return None,
+    })
 }
 
 // --- addition ----------------------------------------------------------------
@@ -444,7 +655,7 @@ where
     normal(back, Color::new(r, g, b, front.a), opacity)
 }
 
-fn mul8(a: i32, b: i32) -> u8 {
+pub(crate) fn mul8(a: i32, b: i32) -> u8 {
     let t = a * b + 0x80;
     let r = ((t >> 8) + t) >> 8;
     r as u8
@@ -456,7 +667,7 @@ fn div8(a: i32, b: i32) -> u8 {
     r as u8
 }
 
-fn blend8(back: u8, src: u8, opacity: u8) -> u8 {
+pub(crate) fn blend8(back: u8, src: u8, opacity: u8) -> u8 {
     let src_x = src as i32;
     let back_x = back as i32;
     let a = src_x - back_x;
@@ -475,7 +686,7 @@ fn normal(back: Color, front: Color, opacity: u8) -> Color {
     }
 
     let front_a = mul8(front.a_i32(), opacity as i32) as i32;
-    let res_a = front_a as i32 + back.a_i32() - mul8(back.a_i32(), front_a) as i32;
+    let res_a = front_a + back.a_i32() - mul8(back.a_i32(), front_a) as i32;
 
     let res_r = back.r_i32() + ((front.r_i32() - back.r_i32()) * front_a) / res_a;
     let res_g = back.g_i32() + ((front.g_i32() - back.g_i32()) * front_a) / res_a;
@@ -644,3 +855,161 @@ fn set_saturation(r: f64, g: f64, b: f64, sat: f64) -> (f64, f64, f64) {
     col[min] = 0.0;
     (col[0], col[1], col[2])
 }
+
+#[test]
+fn test_blend_image() {
+    // Straightforward per-pixel implementation which the optimized
+    // compositor must match exactly.
+    fn reference(
+        target: &mut [u8],
+        target_size: (u16, u16),
+        source: &[u8],
+        source_size: (u16, u16),
+        origin: (i16, i16),
+        blend_fn: BlendFn,
+        opacity: u8,
+    ) {
+        for y in 0..source_size.1 {
+            for x in 0..source_size.0 {
+                let (Some(target_x), Some(target_y)) = (
+                    x.checked_add_signed(origin.0),
+                    y.checked_add_signed(origin.1),
+                ) else {
+                    continue;
+                };
+                if target_x >= target_size.0 || target_y >= target_size.1 {
+                    continue;
+                }
+                let target_index = (usize::from(target_y) * usize::from(target_size.0)
+                    + usize::from(target_x))
+                    * 4;
+                let source_index =
+                    (usize::from(y) * usize::from(source_size.0) + usize::from(x)) * 4;
+                let target_pixel = &mut target[target_index..target_index + 4];
+                let out = blend_fn(
+                    Color::from(&*target_pixel),
+                    Color::from(&source[source_index..source_index + 4]),
+                    opacity,
+                );
+                target_pixel.copy_from_slice(&[out.r, out.g, out.b, out.a]);
+            }
+        }
+    }
+
+    let mut seed = 0x1234_5678u32;
+    let mut random_image = |width: u16, height: u16| -> Vec<u8> {
+        (0..usize::from(width) * usize::from(height) * 4)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let value = (seed >> 16) as u8;
+                // Use many fully opaque and transparent pixels
+                match (i % 4, value % 3) {
+                    (3, 0) => 0,
+                    (3, 1) => 255,
+                    _ => value,
+                }
+            })
+            .collect()
+    };
+    let target_size = (13, 11);
+    let source_size = (7, 9);
+    let row_size = usize::from(target_size.0) * 4;
+    let background = random_image(target_size.0, target_size.1);
+    let source = random_image(source_size.0, source_size.1);
+    let mut opaque_source = source.clone();
+    for pixel in opaque_source.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    for blend_mode in (0..=18).map(BlendMode::from) {
+        let blend_fn = blend_mode_to_blend_fn(blend_mode).unwrap();
+        for opacity in [0, 1, 128, 255] {
+            for origin in [
+                (0, 0),
+                (3, 2),
+                (-4, -5),
+                (10, 8),
+                (-7, 0),
+                (13, 0),
+                (i16::MAX, 0),
+            ] {
+                for source in [&source, &opaque_source] {
+                    let mut expected = background.clone();
+                    reference(
+                        &mut expected,
+                        target_size,
+                        source,
+                        source_size,
+                        origin,
+                        blend_fn,
+                        opacity,
+                    );
+                    let mut target = background.clone();
+                    blend_image(
+                        &mut TargetImage::new(&mut target, Bounds::from_size(target_size)),
+                        source,
+                        source_size,
+                        (origin.0.into(), origin.1.into()),
+                        blend_mode,
+                        opacity,
+                    )
+                    .unwrap();
+                    assert_eq!(
+                        target, expected,
+                        "{:?} {} {:?}",
+                        blend_mode, opacity, origin
+                    );
+                    // Blend into the center of a larger buffer with padding
+                    // at the end of every row
+                    let stride = usize::from(target_size.0 + 5) * 4;
+                    let mut buffer = vec![7; stride * usize::from(target_size.1 + 4)];
+                    for (row, pixels) in background.chunks_exact(row_size).enumerate() {
+                        let start = (row + 2) * stride + 8;
+                        buffer[start..start + row_size].copy_from_slice(pixels);
+                    }
+                    let mut expected_buffer = buffer.clone();
+                    for (row, pixels) in expected.chunks_exact(row_size).enumerate() {
+                        let start = (row + 2) * stride + 8;
+                        expected_buffer[start..start + row_size].copy_from_slice(pixels);
+                    }
+                    blend_image(
+                        &mut TargetImage {
+                            pixels: &mut buffer,
+                            stride,
+                            position: (2, 2),
+                            bounds: Bounds::from_size(target_size),
+                        },
+                        source,
+                        source_size,
+                        (origin.0.into(), origin.1.into()),
+                        blend_mode,
+                        opacity,
+                    )
+                    .unwrap();
+                    assert_eq!(buffer, expected_buffer);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_blend_hsl() {
+    let gray = Color::new(128, 128, 128, 255);
+    let red = Color::new(255, 0, 0, 255);
+    let rgba = |color: Color| [color.r, color.g, color.b, color.a];
+    // The hue and saturation of red with the luminosity of gray
+    assert_eq!(rgba(hsl_color(gray, red, 255)), [255, 73, 73, 255]);
+    // Gray has no saturation so only the luminosity of the backdrop stays
+    assert_eq!(rgba(hsl_hue(gray, red, 255)), [128, 128, 128, 255]);
+    assert_eq!(rgba(hsl_saturation(red, gray, 255)), [76, 76, 76, 255]);
+    // Like Aseprite the channels are truncated and the luminosity of
+    // white is slightly less than 1.0
+    assert_eq!(
+        rgba(hsl_luminosity(
+            Color::new(0, 0, 0, 255),
+            Color::new(255, 255, 255, 255),
+            255
+        )),
+        [254, 254, 254, 255]
+    );
+}
@@ -0,0 +1,171 @@
+use std::sync::OnceLock;
+
+use super::{
+    blend::{blend8, mul8},
+    LoadImageError,
+};
+
+/// The pixel format which is written by the render and image loading
+/// functions.
+///
+/// Aseprite composites all layers in straight RGBA8. The pixels are
+/// converted to this format when they are written to the target buffer.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// 8-bit RGBA with straight alpha
+    #[default]
+    Rgba8,
+    /// 8-bit BGRA with straight alpha
+    Bgra8,
+    /// 8-bit RGBA with the color channels multiplied by the alpha
+    PremultipliedRgba8,
+    /// 8-bit RGB. The pixels are blended onto the `background` color.
+    Rgb8 {
+        /// Background color `[r, g, b]`
+        background: [u8; 3],
+    },
+    /// 8-bit luma. The luma of the pixels is blended onto the `background`.
+    Gray8 {
+        /// Background luma
+        background: u8,
+    },
+    /// 32-bit float RGBA with straight alpha. The color channels are
+    /// converted from sRGB to linear light. Every channel is stored as
+    /// a native endian `f32`.
+    Rgba32FLinear,
+}
+
+impl PixelFormat {
+    /// Number of bytes used by a single pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Bgra8 | Self::PremultipliedRgba8 => 4,
+            Self::Rgb8 { .. } => 3,
+            Self::Gray8 { .. } => 1,
+            Self::Rgba32FLinear => 16,
+        }
+    }
+
+    /// Size of a buffer containing `pixel_count` pixels. Returns
+    /// [`LoadImageError::TargetBufferTooSmall`] if the size overflows.
+    pub(crate) fn buffer_size(&self, pixel_count: usize) -> Result<usize, LoadImageError> {
+        pixel_count
+            .checked_mul(self.bytes_per_pixel())
+            .ok_or(LoadImageError::TargetBufferTooSmall)
+    }
+
+    /// Write a single straight RGBA8 pixel to `target` which must be
+    /// exactly [`bytes_per_pixel`](Self::bytes_per_pixel) long.
+    pub(crate) fn write_pixel(&self, [r, g, b, a]: [u8; 4], target: &mut [u8]) {
+        match *self {
+            Self::Rgba8 => target.copy_from_slice(&[r, g, b, a]),
+            Self::Bgra8 => target.copy_from_slice(&[b, g, r, a]),
+            Self::PremultipliedRgba8 => target.copy_from_slice(&[
+                mul8(r.into(), a.into()),
+                mul8(g.into(), a.into()),
+                mul8(b.into(), a.into()),
+                a,
+            ]),
+            Self::Rgb8 { background } => target.copy_from_slice(&[
+                over(r, background[0], a),
+                over(g, background[1], a),
+                over(b, background[2], a),
+            ]),
+            Self::Gray8 { background } => target[0] = over(luma(r, g, b), background, a),
+            Self::Rgba32FLinear => {
+                let table = srgb_to_linear_table();
+                let pixel = [
+                    table[usize::from(r)],
+                    table[usize::from(g)],
+                    table[usize::from(b)],
+                    f32::from(a) / 255.0,
+                ];
+                for (target, channel) in target.chunks_exact_mut(4).zip(pixel) {
+                    target.copy_from_slice(&channel.to_ne_bytes());
+                }
+            }
+        }
+    }
+
+    /// Convert the straight RGBA8 pixels of `source` into this format.
+    pub(crate) fn convert(&self, source: &[u8], target: &mut [u8]) {
+        if *self == Self::Rgba8 {
+            target.copy_from_slice(source);
+            return;
+        }
+        let bytes_per_pixel = self.bytes_per_pixel();
+        for (target, source) in target
+            .chunks_exact_mut(bytes_per_pixel)
+            .zip(source.chunks_exact(4))
+        {
+            self.write_pixel([source[0], source[1], source[2], source[3]], target);
+        }
+    }
+}
+
+/// Blend the channel `value` with the given `alpha` onto an opaque
+/// `background` channel.
+fn over(value: u8, background: u8, alpha: u8) -> u8 {
+    blend8(background, value, alpha)
+}
+
+/// Luma as calculated by Aseprite's `rgba_luma`
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((u32::from(r) * 2126 + u32::from(g) * 7152 + u32::from(b) * 722) / 10000) as u8
+}
+
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })
+}
+
+#[test]
+fn test_write_pixel() {
+    let pixel = [255, 128, 0, 128];
+    let mut target = [0; 16];
+    let cases: [(PixelFormat, &[u8]); 5] = [
+        (PixelFormat::Rgba8, &[255, 128, 0, 128]),
+        (PixelFormat::Bgra8, &[0, 128, 255, 128]),
+        (PixelFormat::PremultipliedRgba8, &[128, 64, 0, 128]),
+        (
+            PixelFormat::Rgb8 {
+                background: [0, 0, 255],
+            },
+            &[128, 64, 127],
+        ),
+        (PixelFormat::Gray8 { background: 0 }, &[73]),
+    ];
+    for (format, expected) in cases {
+        let target = &mut target[..format.bytes_per_pixel()];
+        format.write_pixel(pixel, target);
+        assert_eq!(target, expected, "{:?}", format);
+    }
+    // Opaque and transparent pixels are not affected by the background
+    let format = PixelFormat::Rgb8 {
+        background: [1, 2, 3],
+    };
+    let mut target = [0; 3];
+    format.write_pixel([10, 20, 30, 255], &mut target);
+    assert_eq!(target, [10, 20, 30]);
+    format.write_pixel([10, 20, 30, 0], &mut target);
+    assert_eq!(target, [1, 2, 3]);
+
+    let mut target = [0; 16];
+    PixelFormat::Rgba32FLinear.write_pixel([255, 0, 188, 51], &mut target);
+    let channels: Vec<f32> = target
+        .chunks_exact(4)
+        .map(|c| f32::from_ne_bytes(c.try_into().unwrap()))
+        .collect();
+    assert_eq!(channels[..2], [1.0, 0.0]);
+    assert!((channels[2] - 0.5).abs() < 0.01);
+    assert!((channels[3] - 0.2).abs() < 1e-6);
+}
@@ -0,0 +1,300 @@
+use crate::binary::{blend_mode::BlendMode, color_depth::ColorDepth, palette::Palette};
+
+use super::{
+    blend::{mul8, Bounds},
+    decompress, AsepriteFile, CelType, Frame, FrameCel, LayerSelection, LoadImageError, RenderItem,
+};
+
+/// A frame of an indexed sprite rendered to palette indices
+///
+/// This is created by [`AsepriteFile::render_frame_indexed`].
+#[derive(Debug, Clone)]
+pub struct IndexedFrame {
+    /// One palette index per pixel, row by row
+    pub indices: Vec<u8>,
+    /// Palette used by the frame
+    pub palette: Palette,
+    /// Index of the transparent color. Pixels which are not covered by
+    /// any layer have this index.
+    pub transparent_index: u8,
+}
+
+impl AsepriteFile<'_> {
+    /// Render a frame of an indexed sprite to palette indices instead of
+    /// RGBA pixels.
+    ///
+    /// The layers are selected and ordered like [`render_frame`](Self::render_frame).
+    /// Like in Aseprite a pixel of a layer replaces the pixel below it
+    /// unless it has the transparent index and the layer is not the
+    /// background layer.
+    ///
+    /// Mixing colors is not possible with palette indices. Layers and cels
+    /// must use the [`BlendMode::Normal`] blend mode and must either be
+    /// fully opaque or fully transparent. Otherwise
+    /// [`LoadImageError::UnsupportedIndexedBlending`] is returned.
+    /// Files which are not indexed return
+    /// [`LoadImageError::UnsupportedColorDepth`].
+    pub fn render_frame_indexed(
+        &self,
+        frame_index: usize,
+        layers: &LayerSelection,
+    ) -> Result<IndexedFrame, LoadImageError> {
+        if self.file.header.color_depth != ColorDepth::Indexed {
+            return Err(LoadImageError::UnsupportedColorDepth);
+        }
+        let frame = self
+            .frames
+            .get(frame_index)
+            .ok_or(LoadImageError::FrameIndexOutOfRange(frame_index))?;
+        let palette = self
+            .palette(frame_index)
+            .ok_or(LoadImageError::MissingPalette)?;
+        let transparent_index = self.file.header.transparent_index;
+        let (width, height) = self.size();
+        let mut indices = vec![transparent_index; usize::from(width) * usize::from(height)];
+        self.render_indexed_layers(frame, None, layers, &mut indices)?;
+        Ok(IndexedFrame {
+            indices,
+            palette: palette.clone(),
+            transparent_index,
+        })
+    }
+
+    /// Draw the palette indices of all children of the given `parent`
+    /// layer into `target`.
+    fn render_indexed_layers(
+        &self,
+        frame: &Frame,
+        parent: Option<usize>,
+        layers: &LayerSelection,
+        target: &mut [u8],
+    ) -> Result<(), LoadImageError> {
+        let mut items = Vec::new();
+        self.render_items(frame, parent, layers, &mut items);
+        for (_, _, _, item) in items {
+            match item {
+                RenderItem::Group(layer_index) => {
+                    // Normal blending of an opaque group is the same as
+                    // drawing its children directly. Groups without valid
+                    // blend values always draw their children directly.
+                    let drawn = match self.group_blending(&self.layers[layer_index]) {
+                        Some((blend_mode, opacity)) => is_drawn(layer_index, blend_mode, opacity)?,
+                        None => true,
+                    };
+                    if drawn {
+                        self.render_indexed_layers(frame, Some(layer_index), layers, target)?;
+                    }
+                }
+                RenderItem::Cel(cel_index) => {
+                    let cel = &frame.cels[cel_index];
+                    let layer = &self.layers[cel.layer_index];
+                    let opacity = mul8(cel.opacity.into(), layer.opacity.into());
+                    if is_drawn(cel.layer_index, layer.blend_mode, opacity)? {
+                        let skip =
+                            (!layer.background).then_some(self.file.header.transparent_index);
+                        self.draw_indexed_cel(cel, skip, target)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the palette indices of a cel into the frame `target`. Pixels
+    /// with the `skip` index are not drawn.
+    fn draw_indexed_cel(
+        &self,
+        cel: &FrameCel,
+        skip: Option<u8>,
+        target: &mut [u8],
+    ) -> Result<(), LoadImageError> {
+        let (width, height) = self.size();
+        let bounds = cel.bounds().intersect(&Bounds::from_size((width, height)));
+        if bounds.is_empty() {
+            return Ok(());
+        }
+        let cel_width = usize::from(cel.size.0);
+        let mut indices = vec![0; cel_width * usize::from(cel.size.1)];
+        match cel.cel_type {
+            CelType::Image => {
+                let image = self
+                    .images
+                    .get(cel.image_index)
+                    .ok_or(LoadImageError::ImageIndexOutOfRange(cel.image_index))?;
+                decode_indices(&image.data, image.compressed, &mut indices)?;
+            }
+            CelType::Tilemap => {
+                let empty = [self.file.header.transparent_index];
+                self.draw_tilemap(cel.image_index, &mut indices, &empty, |data, _, target| {
+                    decode_indices(data, true, target)
+                })?;
+            }
+        }
+        let frame_width = usize::from(width);
+        for y in bounds.y0..bounds.y1 {
+            let source_y = (y - i32::from(cel.origin.1)) as usize;
+            for x in bounds.x0..bounds.x1 {
+                let source_x = (x - i32::from(cel.origin.0)) as usize;
+                let index = indices[source_y * cel_width + source_x];
+                if Some(index) != skip {
+                    target[y as usize * frame_width + x as usize] = index;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if a layer or cel with the given blend mode and opacity changes
+/// the palette indices of a frame.
+fn is_drawn(
+    layer_index: usize,
+    blend_mode: BlendMode,
+    opacity: u8,
+) -> Result<bool, LoadImageError> {
+    match (blend_mode, opacity) {
+        (_, 0) => Ok(false),
+        (BlendMode::Normal, 255) => Ok(true),
+        _ => Err(LoadImageError::UnsupportedIndexedBlending(layer_index)),
+    }
+}
+
+/// Copy the palette indices of an indexed image into `target` which must
+/// have the size of the image.
+fn decode_indices(data: &[u8], compressed: bool, target: &mut [u8]) -> Result<(), LoadImageError> {
+    if compressed {
+        decompress(data, target)
+    } else if data.len() == target.len() {
+        target.copy_from_slice(data);
+        Ok(())
+    } else {
+        Err(LoadImageError::InvalidImageData)
+    }
+}
+
+#[test]
+fn test_render_frame_indexed() {
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("aseprite") {
+            continue;
+        }
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let result = file.render_frame_indexed(0, &LayerSelection::Visible);
+        if file.file.header.color_depth != ColorDepth::Indexed {
+            assert!(matches!(result, Err(LoadImageError::UnsupportedColorDepth)));
+            continue;
+        }
+        // Looking up the indices in the palette gives the RGBA frame
+        for frame_index in 0..file.frames().len() {
+            let frame = file
+                .render_frame_indexed(frame_index, &LayerSelection::Visible)
+                .unwrap();
+            let (width, height) = file.size();
+            let mut expected = vec![0; usize::from(width) * usize::from(height) * 4];
+            file.render_frame(frame_index, &mut expected, &LayerSelection::Visible)
+                .unwrap();
+            for (pixel, &index) in expected.chunks_exact(4).zip(&frame.indices) {
+                let color = frame.palette.colors[usize::from(index)];
+                if pixel[3] == 0 {
+                    continue;
+                }
+                assert_eq!(
+                    pixel,
+                    [color.red, color.green, color.blue, color.alpha],
+                    "{:?} frame {}",
+                    path,
+                    frame_index
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_render_frame_indexed_background() {
+    let data = std::fs::read("./tests/indexed_background.aseprite").unwrap();
+    let mut file = AsepriteFile::load(&data).unwrap();
+    let frame = file
+        .render_frame_indexed(0, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(frame.indices, [0, 2]);
+    assert_eq!(frame.transparent_index, 0);
+    assert_eq!(frame.palette.colors[2].green, 255);
+    // Layers without the background layer keep the transparent index
+    let frame = file
+        .render_frame_indexed(0, &LayerSelection::Mask(vec![false, true]))
+        .unwrap();
+    assert_eq!(frame.indices, [0, 2]);
+    let frame = file
+        .render_frame_indexed(0, &LayerSelection::Mask(vec![true, false]))
+        .unwrap();
+    assert_eq!(frame.indices, [0, 1]);
+
+    // Transparent layers are skipped
+    file.layers[1].opacity = 0;
+    let frame = file
+        .render_frame_indexed(0, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(frame.indices, [0, 1]);
+    // Colors can't be mixed
+    file.layers[1].opacity = 128;
+    assert!(matches!(
+        file.render_frame_indexed(0, &LayerSelection::Visible),
+        Err(LoadImageError::UnsupportedIndexedBlending(1))
+    ));
+    file.layers[1].opacity = 255;
+    file.layers[1].blend_mode = BlendMode::Multiply;
+    assert!(matches!(
+        file.render_frame_indexed(0, &LayerSelection::Visible),
+        Err(LoadImageError::UnsupportedIndexedBlending(1))
+    ));
+    assert!(matches!(
+        file.render_frame_indexed(1, &LayerSelection::Visible),
+        Err(LoadImageError::FrameIndexOutOfRange(1))
+    ));
+}
+
+#[test]
+fn test_render_frame_indexed_group() {
+    use crate::binary::scalars::Color;
+    use crate::builder::{Cel, LayerOptions, SpriteBuilder};
+
+    let mut sprite = SpriteBuilder::new(2, 1, ColorDepth::Indexed);
+    let group = sprite
+        .add_group(
+            "Group",
+            &LayerOptions {
+                opacity: 128,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let layer = sprite
+        .add_layer(
+            "Layer",
+            &LayerOptions {
+                parent: Some(group),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let frame = sprite.add_frame(100);
+    sprite
+        .add_cel(frame, layer, Cel::new(2, 1, vec![1, 0]))
+        .unwrap();
+    sprite.set_palette(&[Color::default(); 2]).unwrap();
+    let data = sprite.build().unwrap();
+    let mut file = AsepriteFile::load(&data).unwrap();
+    assert!(matches!(
+        file.render_frame_indexed(0, &LayerSelection::Visible),
+        Err(LoadImageError::UnsupportedIndexedBlending(0))
+    ));
+    // The group opacity is ignored unless the header marks it as valid
+    file.file.header.flags = 1;
+    let frame = file
+        .render_frame_indexed(0, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(frame.indices, [1, 0]);
+}
@@ -0,0 +1,385 @@
+use std::fmt::Write;
+
+use crate::binary::{
+    blend_mode::BlendMode, chunks::tags::AnimationDirection, chunks::user_data::UserDataChunk,
+    scalars::Color,
+};
+
+use super::{AsepriteFile, LayerType, SpriteSheet};
+
+/// The two variants of the JSON data written by `aseprite --sheet --data`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// `frames` is an object using the frame names as keys
+    #[default]
+    Hash,
+    /// `frames` is an array and the frame names are stored in the
+    /// `filename` field of every frame
+    Array,
+}
+
+/// Options for [`SpriteSheet::to_json`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// The variant of the JSON data
+    pub format: JsonFormat,
+    /// File name of the source file (e.g. `player.aseprite`). This is used
+    /// to generate the frame names the same way Aseprite does by default:
+    /// `{title} {frame}.{extension}` or `{title}.{extension}` if the file
+    /// only contains a single frame.
+    pub filename: String,
+    /// File name of the sprite sheet image (`meta.image`)
+    pub image: String,
+}
+
+impl SpriteSheet {
+    /// Create the same JSON data as `aseprite --sheet --data` does.
+    ///
+    /// The `file` must be the file this sheet was created from.
+    pub fn to_json(&self, file: &AsepriteFile<'_>, options: &JsonOptions) -> String {
+        let mut out = String::new();
+        match options.format {
+            JsonFormat::Hash => out.push_str("{ \"frames\": {\n"),
+            JsonFormat::Array => out.push_str("{ \"frames\": [\n"),
+        }
+        for (i, frame) in self.frames.iter().enumerate() {
+            let name = frame_name(&options.filename, frame.frame_index, file.frames.len());
+            match options.format {
+                JsonFormat::Hash => {
+                    let _ = writeln!(out, "   {}: {{", string(&name));
+                }
+                JsonFormat::Array => {
+                    let _ = write!(out, "   {{\n    \"filename\": {},\n", string(&name));
+                }
+            }
+            let src = frame.source_rect;
+            let _ = write!(
+                out,
+                "    \"frame\": {},\n    \"rotated\": false,\n    \"trimmed\": {},\n    \
+                 \"spriteSourceSize\": {},\n    \"sourceSize\": {{ \"w\": {}, \"h\": {} }},\n    \
+                 \"duration\": {}\n   }}",
+                rect(
+                    frame.rect.x,
+                    frame.rect.y,
+                    frame.rect.width,
+                    frame.rect.height
+                ),
+                frame.trimmed,
+                rect(src.x, src.y, src.width, src.height),
+                frame.source_size.0,
+                frame.source_size.1,
+                frame.duration,
+            );
+            out.push_str(separator(i, self.frames.len()));
+        }
+        match options.format {
+            JsonFormat::Hash => out.push_str(" },\n"),
+            JsonFormat::Array => out.push_str(" ],\n"),
+        }
+
+        let _ = write!(
+            out,
+            " \"meta\": {{\n  \"app\": \"https://github.com/bikeshedder/aseprite-loader\",\n  \
+             \"version\": \"{}\",\n  \"image\": {},\n  \"format\": \"RGBA8888\",\n  \
+             \"size\": {{ \"w\": {}, \"h\": {} }},\n  \"scale\": \"1\",\n",
+            env!("CARGO_PKG_VERSION"),
+            string(&options.image),
+            self.width,
+            self.height,
+        );
+
+        out.push_str("  \"frameTags\": [\n");
+        for (i, tag) in file.file.tags.iter().enumerate() {
+            let _ = write!(
+                out,
+                "   {{ \"name\": {}, \"from\": {}, \"to\": {}, \"direction\": \"{}\"",
+                string(&tag.name),
+                tag.frames.start(),
+                tag.frames.end(),
+                direction(tag.animation_direction),
+            );
+            if tag.animation_repeat > 0 {
+                let _ = write!(out, ", \"repeat\": \"{}\"", tag.animation_repeat);
+            }
+            #[allow(deprecated)]
+            let [red, green, blue] = tag.color;
+            let tag_color = Color {
+                red,
+                green,
+                blue,
+                alpha: 255,
+            };
+            write_user_data(&mut out, tag.user_data.as_ref(), Some(tag_color));
+            out.push_str(" }");
+            out.push_str(separator(i, file.file.tags.len()));
+        }
+        out.push_str("  ],\n");
+
+        out.push_str("  \"layers\": [\n");
+        // Layers of unsupported types are not part of `file.layers`
+        let file_layers = file.file.layers.iter().filter(|layer| {
+            matches!(
+                layer.layer_type,
+                LayerType::Normal | LayerType::Group | LayerType::Tilemap
+            )
+        });
+        for (i, (layer, file_layer)) in file.layers.iter().zip(file_layers).enumerate() {
+            let _ = write!(out, "   {{ \"name\": {}", string(&layer.name));
+            if let Some(parent) = layer.parent {
+                let _ = write!(out, ", \"group\": {}", string(&file.layers[parent].name));
+            }
+            if layer.layer_type != LayerType::Group {
+                let _ = write!(
+                    out,
+                    ", \"opacity\": {}, \"blendMode\": \"{}\"",
+                    layer.opacity,
+                    blend_mode(layer.blend_mode)
+                );
+            }
+            write_user_data(&mut out, file_layer.user_data.as_ref(), None);
+            out.push_str(" }");
+            out.push_str(separator(i, file.layers.len()));
+        }
+        out.push_str("  ],\n");
+
+        out.push_str("  \"slices\": [\n");
+        for (i, slice) in file.file.slices.iter().enumerate() {
+            let _ = write!(out, "   {{ \"name\": {}", string(&slice.name));
+            // Slices are blue by default
+            let default_color = Color {
+                red: 0,
+                green: 0,
+                blue: 255,
+                alpha: 255,
+            };
+            write_user_data(&mut out, slice.user_data.as_ref(), Some(default_color));
+            out.push_str(", \"keys\": [");
+            for (j, key) in slice.slice_keys.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{{ \"frame\": {}, \"bounds\": {}",
+                    key.frame_number,
+                    rect(key.x, key.y, key.width, key.height)
+                );
+                if let Some(center) = key.nine_patch {
+                    let _ = write!(
+                        out,
+                        ", \"center\": {}",
+                        rect(center.x, center.y, center.width, center.height)
+                    );
+                }
+                if let Some(pivot) = key.pivot {
+                    let _ = write!(
+                        out,
+                        ", \"pivot\": {{ \"x\": {}, \"y\": {} }}",
+                        pivot.x, pivot.y
+                    );
+                }
+                out.push_str(" }");
+                if j + 1 < slice.slice_keys.len() {
+                    out.push_str(", ");
+                }
+            }
+            out.push_str("] }");
+            out.push_str(separator(i, file.file.slices.len()));
+        }
+        out.push_str("  ]\n }\n}\n");
+        out
+    }
+}
+
+/// Generate the frame name the same way Aseprite does by default.
+fn frame_name(filename: &str, frame_index: usize, frame_count: usize) -> String {
+    if frame_count == 1 {
+        return filename.to_owned();
+    }
+    match filename.rsplit_once('.') {
+        Some((title, extension)) => format!("{} {}.{}", title, frame_index, extension),
+        None => format!("{} {}", filename, frame_index),
+    }
+}
+
+fn separator(index: usize, len: usize) -> &'static str {
+    if index + 1 < len {
+        ",\n"
+    } else {
+        "\n"
+    }
+}
+
+fn rect(x: impl std::fmt::Display, y: impl std::fmt::Display, w: u32, h: u32) -> String {
+    format!(
+        "{{ \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {} }}",
+        x, y, w, h
+    )
+}
+
+/// Write the `color` and `data` fields. If the user data does not contain
+/// a color `default_color` is used instead. Like in Aseprite transparent
+/// colors and empty texts are not written.
+fn write_user_data(
+    out: &mut String,
+    user_data: Option<&UserDataChunk<'_>>,
+    default_color: Option<Color>,
+) {
+    let color = user_data.and_then(|u| u.color).or(default_color);
+    if let Some(c) = color.filter(|c| c.alpha > 0) {
+        let _ = write!(
+            out,
+            ", \"color\": \"#{:02x}{:02x}{:02x}{:02x}\"",
+            c.red, c.green, c.blue, c.alpha
+        );
+    }
+    let text = user_data.and_then(|u| u.text.as_deref());
+    if let Some(text) = text.filter(|text| !text.is_empty()) {
+        let _ = write!(out, ", \"data\": {}", string(text));
+    }
+}
+
+/// Quote and escape a JSON string.
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn direction(direction: AnimationDirection) -> &'static str {
+    match direction {
+        AnimationDirection::Reverse => "reverse",
+        AnimationDirection::PingPong => "pingpong",
+        AnimationDirection::PingPongReverse => "pingpong_reverse",
+        AnimationDirection::Forward | AnimationDirection::Unknown(_) => "forward",
+    }
+}
+
+fn blend_mode(blend_mode: BlendMode) -> &'static str {
+    match blend_mode {
+        BlendMode::Normal | BlendMode::Unknown(_) => "normal",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Screen => "screen",
+        BlendMode::Overlay => "overlay",
+        BlendMode::Darken => "darken",
+        BlendMode::Lighten => "lighten",
+        BlendMode::ColorDodge => "color_dodge",
+        BlendMode::ColorBurn => "color_burn",
+        BlendMode::HardLight => "hard_light",
+        BlendMode::SoftLight => "soft_light",
+        BlendMode::Difference => "difference",
+        BlendMode::Exclusion => "exclusion",
+        BlendMode::Hue => "hue",
+        BlendMode::Saturation => "saturation",
+        BlendMode::Color => "color",
+        BlendMode::Luminosity => "luminosity",
+        BlendMode::Addition => "addition",
+        BlendMode::Subtract => "subtract",
+        BlendMode::Divide => "divide",
+    }
+}
+
+#[test]
+fn test_frame_name() {
+    assert_eq!(frame_name("player.aseprite", 0, 1), "player.aseprite");
+    assert_eq!(frame_name("player.aseprite", 3, 4), "player 3.aseprite");
+    assert_eq!(frame_name("player", 3, 4), "player 3");
+}
+
+#[test]
+fn test_string() {
+    assert_eq!(string("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+}
+
+#[test]
+fn test_json() {
+    let data = std::fs::read("./tests/groups.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let sheet = file.sprite_sheet(&Default::default()).unwrap();
+    let options = JsonOptions {
+        format: JsonFormat::Array,
+        filename: "groups.aseprite".to_owned(),
+        image: "groups.png".to_owned(),
+    };
+    let json = sheet.to_json(&file, &options);
+    let expected = format!(
+        r#"{{ "frames": [
+   {{
+    "filename": "groups.aseprite",
+    "frame": {{ "x": 0, "y": 0, "w": 2, "h": 1 }},
+    "rotated": false,
+    "trimmed": false,
+    "spriteSourceSize": {{ "x": 0, "y": 0, "w": 2, "h": 1 }},
+    "sourceSize": {{ "w": 2, "h": 1 }},
+    "duration": 100
+   }}
+ ],
+ "meta": {{
+  "app": "https://github.com/bikeshedder/aseprite-loader",
+  "version": "{}",
+  "image": "groups.png",
+  "format": "RGBA8888",
+  "size": {{ "w": 2, "h": 1 }},
+  "scale": "1",
+  "frameTags": [
+  ],
+  "layers": [
+   {{ "name": "Background", "opacity": 255, "blendMode": "normal" }},
+   {{ "name": "body" }},
+   {{ "name": "arm", "group": "body" }},
+   {{ "name": "sleeve", "group": "arm", "opacity": 255, "blendMode": "normal" }},
+   {{ "name": "glove", "group": "arm", "opacity": 255, "blendMode": "normal" }},
+   {{ "name": "hidden" }},
+   {{ "name": "ghost", "group": "hidden", "opacity": 255, "blendMode": "normal" }}
+  ],
+  "slices": [
+  ]
+ }}
+}}
+"#,
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn test_json_hash() {
+    let data = std::fs::read("./tests/user_data.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let sheet = file.sprite_sheet(&Default::default()).unwrap();
+    let options = JsonOptions {
+        filename: "user_data.aseprite".to_owned(),
+        ..Default::default()
+    };
+    let json = sheet.to_json(&file, &options);
+    assert!(json.starts_with("{ \"frames\": {\n   \"user_data"));
+    assert!(json.contains("\"data\": \"tag_data_1\""));
+    assert!(json.contains("\"data\": \"slice_data\""));
+    assert!(json.contains(
+        "{ \"name\": \"Layer 1\", \"opacity\": 255, \"blendMode\": \"normal\", \
+         \"data\": \"layer_data\" }"
+    ));
+
+    // Layer colors are written if they are not transparent
+    let mut file = file;
+    let color = Color {
+        red: 1,
+        green: 2,
+        blue: 3,
+        alpha: 255,
+    };
+    file.file.layers[0].user_data.as_mut().unwrap().color = Some(color);
+    let json = sheet.to_json(&file, &options);
+    assert!(json.contains("\"blendMode\": \"normal\", \"color\": \"#010203ff\", \"data\""));
+}
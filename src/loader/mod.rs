@@ -4,13 +4,33 @@
 
 use flate2::Decompress;
 use std::{
+    borrow::Cow,
     collections::hash_map::DefaultHasher,
     collections::HashMap,
     hash::{Hash, Hasher},
+    io::Read,
     ops::RangeInclusive,
+    path::Path,
 };
 
+mod animation;
 mod blend;
+mod format;
+mod indexed;
+mod json;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod recolor;
+mod renderer;
+mod sheet;
+
+pub use animation::{Animation, AnimationEvents, AnimationPlayer};
+pub use format::PixelFormat;
+pub use indexed::IndexedFrame;
+pub use json::{JsonFormat, JsonOptions};
+pub use recolor::Recolor;
+pub use renderer::Renderer;
+pub use sheet::{Rect, SheetFrame, SheetLayout, SheetOptions, SpriteSheet};
 
 use crate::{
     binary::{
@@ -20,13 +40,16 @@ use crate::{
             layer::{LayerFlags, LayerType},
             slice::SliceChunk,
             tags::AnimationDirection,
+            tileset::TilesetFlags,
         },
         color_depth::ColorDepth,
-        file::{parse_file, File},
+        errors::ParseError,
+        file::{parse_file_with_options, File},
         image::Image,
         palette::Palette,
+        raw_file::ParseOptions,
     },
-    loader::blend::{blend_mode_to_blend_fn, Color},
+    loader::blend::{blend_image, mul8, Bounds, TargetImage},
 };
 
 /// This can be used to load an Aseprite file.
@@ -42,6 +65,75 @@ pub struct AsepriteFile<'a> {
     pub tags: Vec<Tag>,
     /// All images in the file
     pub images: Vec<Image<'a>>,
+    /// All tilemaps in the file
+    pub tilemaps: Vec<Tilemap<'a>>,
+    /// Data which was skipped while loading in lenient mode
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Options for loading an Aseprite file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Abort loading at the first error. Otherwise invalid chunks and
+    /// cels are skipped and reported in [`AsepriteFile::diagnostics`].
+    pub strict: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self { strict: true }
+    }
+}
+
+/// Options for rendering frames and loading images
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Pixel format of the target buffer
+    pub format: PixelFormat,
+    /// Colors which are replaced while decoding the pixels
+    pub recolor: Option<Recolor>,
+}
+
+impl Tilemap<'_> {
+    /// Convert into a tilemap which does not borrow the input
+    pub fn into_owned(self) -> Tilemap<'static> {
+        Tilemap {
+            data: Cow::Owned(self.data.into_owned()),
+            ..self
+        }
+    }
+}
+
+/// The kind of content a [`FrameCel`] refers to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CelType {
+    /// The cel refers to an entry in [`AsepriteFile::images`].
+    Image,
+    /// The cel refers to an entry in [`AsepriteFile::tilemaps`].
+    Tilemap,
+}
+
+/// A tilemap cel
+///
+/// The tiles reference the tiles of a tileset in [`File::tilesets`].
+#[derive(Debug, Clone)]
+pub struct Tilemap<'a> {
+    /// Index into [`File::tilesets`].
+    pub tileset_index: usize,
+    /// Tilemap size in tiles `(width, height)`.
+    pub size: (u16, u16),
+    /// Bits per tile
+    pub bits_per_tile: u16,
+    /// Bitmask for tile ID
+    pub bitmask_tile_id: u32,
+    /// Bitmask for X flip
+    pub bitmask_x_flip: u32,
+    /// Bitmask for Y flip
+    pub bitmask_y_flip: u32,
+    /// Bitmask for diagonal flip (swap X/Y axis)
+    pub bitmask_diagonal_flip: u32,
+    /// ZLIB compressed tile data
+    pub data: Cow<'a, [u8]>,
 }
 
 /// A cel in a frame
@@ -55,8 +147,30 @@ pub struct FrameCel {
     pub size: (u16, u16),
     /// Index into [`AsepriteFile::layers`].
     pub layer_index: usize,
-    /// Index into [`AsepriteFile::images`].
+    /// Index into [`AsepriteFile::images`] or [`AsepriteFile::tilemaps`]
+    /// depending on the [`cel_type`](Self::cel_type).
     pub image_index: usize,
+    /// Whether this cel contains an image or a tilemap.
+    pub cel_type: CelType,
+    /// Cel opacity in the range `0..=255`.
+    pub opacity: u8,
+    /// Z-index of the cel. This is used to change the order of cels
+    /// relative to the cels of other layers.
+    pub z_index: i16,
+}
+
+impl FrameCel {
+    /// The pixels covered by the cel in sprite coordinates
+    fn bounds(&self) -> Bounds {
+        let x0 = i32::from(self.origin.0);
+        let y0 = i32::from(self.origin.1);
+        Bounds {
+            x0,
+            y0,
+            x1: x0 + i32::from(self.size.0),
+            y1: y0 + i32::from(self.size.1),
+        }
+    }
 }
 
 /// A frame in the file
@@ -98,20 +212,109 @@ pub struct Layer {
     pub blend_mode: BlendMode,
     /// Whether the layer is visible in the source file.
     pub visible: bool,
+    /// Whether this is the background layer. Background layers draw the
+    /// transparent color of indexed sprites opaque.
+    pub background: bool,
     /// Layer kind (normal/group/etc).
     pub layer_type: LayerType,
+    /// Index of the parent group in [`AsepriteFile::layers`] or `None`
+    /// if this is a top-level layer.
+    pub parent: Option<usize>,
+}
+
+/// A region of a larger buffer which a frame is rendered into
+///
+/// This allows rendering frames directly into an atlas page or a
+/// framebuffer. Pixels which are outside of the buffer are clipped.
+#[derive(Debug)]
+pub struct RenderTarget<'t> {
+    /// Pixels in the format of the [`RenderOptions`]. The width of the
+    /// buffer in pixels is `stride / bytes_per_pixel` and the last row
+    /// may be shorter than the stride.
+    pub buffer: &'t mut [u8],
+    /// Distance between the rows of the buffer in bytes
+    pub stride: usize,
+    /// Column of the left edge of the frame in the buffer
+    pub x: i32,
+    /// Row of the top edge of the frame in the buffer
+    pub y: i32,
+}
+
+/// A layer group or cel which is composited when rendering a frame.
+#[derive(Debug, Copy, Clone)]
+enum RenderItem {
+    Group(usize),
+    /// Index into [`Frame::cels`]
+    Cel(usize),
+}
+
+/// The palette which is used for decoding indexed pixels
+#[derive(Debug, Copy, Clone)]
+struct IndexedPalette<'p> {
+    palette: &'p Palette,
+    /// The color index which is transparent. This is `None` for
+    /// background layers.
+    transparent_index: Option<u8>,
+}
+
+/// Decoded RGBA8 pixels and the index into [`File::palettes`] of the
+/// palette which was used for decoding them
+type CachedImage = (usize, Vec<u8>);
+
+/// The pixels of the cels which are composited when rendering a frame.
+#[derive(Debug, Copy, Clone)]
+enum CelPixels<'c> {
+    /// Decode every cel when it is composited
+    Decode,
+    /// Use the images and tilemaps decoded by a [`Renderer`]
+    Cached {
+        images: &'c [Option<CachedImage>],
+        tilemaps: &'c [Option<CachedImage>],
+    },
+}
+
+/// Buffers which are used while compositing a frame. Keeping them
+/// around avoids allocations when rendering multiple frames.
+#[derive(Debug, Default)]
+struct Scratch {
+    /// Sorted render items for every level of nested groups
+    items: Vec<Vec<(isize, i16, usize, RenderItem)>>,
+    /// Group buffers for every level of nested groups
+    groups: Vec<Vec<u8>>,
+    /// RGBA8 buffer for a band of rows used for pixel formats other
+    /// than RGBA8
+    band: Vec<u8>,
+}
+
+/// Number of rows which are composited at once for pixel formats other
+/// than RGBA8
+const BAND_HEIGHT: usize = 16;
+
+/// State which is passed down while compositing the layers of a frame.
+#[derive(Debug)]
+struct RenderContext<'c> {
+    frame: &'c Frame,
+    frame_index: usize,
+    layers: &'c LayerSelection,
+    recolor: Option<&'c Recolor>,
+    pixels: CelPixels<'c>,
+    scratch: &'c mut Scratch,
 }
 
 /// Pre-computed layer visibility selection for efficient per-frame filtering.
 ///
 /// Created via [`AsepriteFile::select_layers`].
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum LayerSelection {
-    /// Render visible layers as defined in the aseprite file.
+    /// Render visible layers as defined in the aseprite file. Hidden
+    /// groups also hide all of their children.
+    #[default]
     Visible,
     /// Render all layers regardless of their visibility in the aseprite file.
     All,
-    /// Render layers using a mask.
+    /// Render layers using a mask. The mask is only checked for non-group
+    /// layers. Groups are always composited so selecting a layer does not
+    /// require selecting all of its parent groups, too.
     Mask(Vec<bool>),
 }
 
@@ -120,6 +323,7 @@ impl LayerSelection {
         match self {
             Self::Visible => layer.visible,
             Self::All => true,
+            Self::Mask(_) if layer.layer_type == LayerType::Group => true,
             Self::Mask(mask) => mask.get(layer_index).copied().unwrap_or(false),
         }
     }
@@ -128,36 +332,154 @@ impl LayerSelection {
 impl AsepriteFile<'_> {
     /// Load a aseprite file from a byte slice
     pub fn load(data: &[u8]) -> Result<AsepriteFile<'_>, LoadSpriteError> {
-        let file = parse_file(data).map_err(|e| LoadSpriteError::Parse {
-            message: e.to_string(),
-        })?;
-        let layers: Vec<_> = file
-            .layers
-            .iter()
-            .filter_map(|layer| {
-                if layer.layer_type == LayerType::Normal || layer.layer_type == LayerType::Group {
-                    Some(Layer {
+        Self::load_with_options(data, &LoadOptions::default())
+    }
+    /// Load a aseprite file from a byte slice using the given options
+    pub fn load_with_options<'a>(
+        data: &'a [u8],
+        options: &LoadOptions,
+    ) -> Result<AsepriteFile<'a>, LoadSpriteError> {
+        let parse_options = ParseOptions {
+            strict: options.strict,
+        };
+        let mut file = parse_file_with_options(data, &parse_options)?;
+        let mut diagnostics: Vec<Diagnostic> = std::mem::take(&mut file.diagnostics)
+            .into_iter()
+            .map(Diagnostic::Parse)
+            .collect();
+        let mut report = |e: LoadSpriteError| {
+            if options.strict {
+                Err(e)
+            } else {
+                diagnostics.push(Diagnostic::Load(e));
+                Ok(())
+            }
+        };
+        // Layers of unsupported types are skipped. This map translates the
+        // layer indices used by the file into indices into `layers`.
+        let mut layer_map: HashMap<usize, usize> = HashMap::new();
+        let mut layers: Vec<Layer> = Vec::new();
+        // Stack of the currently open groups. The group at position `n`
+        // is the parent of all following layers with a child level of `n + 1`.
+        let mut groups: Vec<Option<usize>> = Vec::new();
+        for (file_layer_index, layer) in file.layers.iter().enumerate() {
+            let child_level = usize::from(layer.child_level);
+            groups.truncate(child_level);
+            let parent = groups.last().copied().flatten();
+            let layer_index = match layer.layer_type {
+                LayerType::Normal | LayerType::Group | LayerType::Tilemap => {
+                    let layer_index = layers.len();
+                    layers.push(Layer {
                         name: layer.name.to_string(),
                         opacity: layer.opacity,
                         blend_mode: layer.blend_mode,
                         visible: layer.flags.contains(LayerFlags::VISIBLE),
+                        background: layer.flags.contains(LayerFlags::BACKGROUND),
                         layer_type: layer.layer_type,
-                    })
-                } else {
-                    None
+                        parent,
+                    });
+                    let _ = layer_map.insert(file_layer_index, layer_index);
+                    Some(layer_index)
                 }
-            })
-            .collect();
+                _ => None,
+            };
+            if layer.layer_type == LayerType::Group {
+                // Malformed files might skip levels. Fill the gaps so the
+                // group ends up at the position matching its child level.
+                groups.resize(child_level, parent);
+                groups.push(layer_index);
+            }
+        }
+
+        let pixel_size = file.header.color_depth.pixel_size().unwrap_or(0);
+        let mut invalid_tilesets: Vec<u32> = Vec::new();
+        for tileset in &file.tilesets {
+            let Some(tiles) = &tileset.tiles else {
+                continue;
+            };
+            let size = usize::try_from(tileset.number_of_tiles)
+                .ok()
+                .and_then(|n| n.checked_mul(usize::from(tileset.width)))
+                .and_then(|n| n.checked_mul(usize::from(tileset.height) * pixel_size));
+            if !size.is_some_and(|size| is_valid_data_size(&tiles.data, true, size)) {
+                report(LoadSpriteError::InvalidTileset(tileset.id))?;
+                invalid_tilesets.push(tileset.id);
+            }
+        }
 
         let mut image_vec: Vec<Image<'_>> = Vec::new();
-        let mut image_map: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut tilemaps: Vec<Tilemap<'_>> = Vec::new();
+        let mut image_map: HashMap<(usize, usize), (CelType, usize)> = HashMap::new();
 
         for (frame_index, frame) in file.frames.iter().enumerate() {
             for cel in frame.cels.iter().filter_map(|x| x.as_ref()) {
-                if let CelContent::Image(image) = &cel.content {
-                    let image_index = image_vec.len();
-                    image_vec.push(image.clone());
-                    let _ = image_map.insert((frame_index, cel.layer_index.into()), image_index);
+                let key = (frame_index, cel.layer_index.into());
+                match &cel.content {
+                    CelContent::Image(image) => {
+                        let size =
+                            usize::from(image.width) * usize::from(image.height) * pixel_size;
+                        if !is_valid_data_size(&image.data, image.compressed, size) {
+                            report(LoadSpriteError::InvalidCel {
+                                frame: frame_index,
+                                layer: cel.layer_index.into(),
+                            })?;
+                            continue;
+                        }
+                        let _ = image_map.insert(key, (CelType::Image, image_vec.len()));
+                        image_vec.push(image.clone());
+                    }
+                    CelContent::CompressedTilemap {
+                        width,
+                        height,
+                        bits_per_tile,
+                        bitmask_tile_id,
+                        bitmask_x_flip,
+                        bitmask_y_flip,
+                        bitmask_diagonal_flip,
+                        data,
+                    } => {
+                        let tileset_id = file
+                            .layers
+                            .get(usize::from(cel.layer_index))
+                            .and_then(|layer| layer.tileset_index);
+                        let Some(tileset_index) = file
+                            .tilesets
+                            .iter()
+                            .position(|tileset| Some(tileset.id) == tileset_id)
+                        else {
+                            report(LoadSpriteError::MissingTileset {
+                                frame: frame_index,
+                                layer: cel.layer_index.into(),
+                            })?;
+                            continue;
+                        };
+                        // The invalid tileset has already been reported
+                        if invalid_tilesets.contains(&file.tilesets[tileset_index].id) {
+                            continue;
+                        }
+                        let size = usize::from(*width)
+                            * usize::from(*height)
+                            * usize::from(*bits_per_tile / 8);
+                        if !is_valid_data_size(data, true, size) {
+                            report(LoadSpriteError::InvalidCel {
+                                frame: frame_index,
+                                layer: cel.layer_index.into(),
+                            })?;
+                            continue;
+                        }
+                        let _ = image_map.insert(key, (CelType::Tilemap, tilemaps.len()));
+                        tilemaps.push(Tilemap {
+                            tileset_index,
+                            size: (*width, *height),
+                            bits_per_tile: *bits_per_tile,
+                            bitmask_tile_id: *bitmask_tile_id,
+                            bitmask_x_flip: *bitmask_x_flip,
+                            bitmask_y_flip: *bitmask_y_flip,
+                            bitmask_diagonal_flip: *bitmask_diagonal_flip,
+                            data: data.clone(),
+                        });
+                    }
+                    _ => {}
                 }
             }
         }
@@ -181,29 +503,60 @@ impl AsepriteFile<'_> {
         for (index, frame) in file.frames.iter().enumerate() {
             let mut cels: Vec<FrameCel> = Vec::new();
             for cel in frame.cels.iter().filter_map(|x| x.as_ref()) {
-                let image_index = match cel.content {
-                    CelContent::Image(_) => image_map[&(index, cel.layer_index.into())],
-                    CelContent::LinkedCel { frame_position } => *image_map
-                        .get(&(frame_position.into(), cel.layer_index.into()))
-                        .ok_or_else(|| LoadSpriteError::Parse {
-                            message: format!(
-                                "invalid linked cel at frame {} layer {}",
-                                index, cel.layer_index
-                            ),
-                        })?,
+                let Some(&layer_index) = layer_map.get(&cel.layer_index.into()) else {
+                    continue;
+                };
+                let (cel_type, image_index) = match cel.content {
+                    CelContent::Image(_) | CelContent::CompressedTilemap { .. } => {
+                        // Cels missing from the map have been skipped
+                        // and already been reported.
+                        let Some(&entry) = image_map.get(&(index, cel.layer_index.into())) else {
+                            continue;
+                        };
+                        entry
+                    }
+                    CelContent::LinkedCel { frame_position } => {
+                        match image_map.get(&(frame_position.into(), cel.layer_index.into())) {
+                            Some(&entry) => entry,
+                            None => {
+                                report(LoadSpriteError::InvalidLinkedCel {
+                                    frame: index,
+                                    layer: cel.layer_index.into(),
+                                })?;
+                                continue;
+                            }
+                        }
+                    }
                     _ => {
-                        return Err(LoadSpriteError::Parse {
-                            message: "invalid cel".to_owned(),
-                        })
+                        report(LoadSpriteError::InvalidCel {
+                            frame: index,
+                            layer: cel.layer_index.into(),
+                        })?;
+                        continue;
+                    }
+                };
+                let size = match cel_type {
+                    CelType::Image => {
+                        let image = &image_vec[image_index];
+                        (image.width, image.height)
+                    }
+                    CelType::Tilemap => {
+                        let tilemap = &tilemaps[image_index];
+                        let tileset = &file.tilesets[tilemap.tileset_index];
+                        (
+                            tilemap.size.0.saturating_mul(tileset.width),
+                            tilemap.size.1.saturating_mul(tileset.height),
+                        )
                     }
                 };
-                let width = image_vec[image_index].width;
-                let height = image_vec[image_index].height;
                 cels.push(FrameCel {
                     origin: (cel.x, cel.y),
-                    size: (width, height),
-                    layer_index: cel.layer_index.into(),
+                    size,
+                    layer_index,
                     image_index,
+                    cel_type,
+                    opacity: cel.opacity,
+                    z_index: cel.z_index,
                 });
             }
 
@@ -220,8 +573,45 @@ impl AsepriteFile<'_> {
             layers,
             frames,
             images: image_vec,
+            tilemaps,
+            diagnostics,
         })
     }
+    /// Load a aseprite file from the given path
+    ///
+    /// The returned file owns all of its data.
+    pub fn load_from_path(
+        path: impl AsRef<Path>,
+    ) -> Result<AsepriteFile<'static>, LoadSpriteError> {
+        let data = std::fs::read(path)?;
+        Ok(AsepriteFile::load(&data)?.into_owned())
+    }
+    /// Load a aseprite file from a reader
+    ///
+    /// The returned file owns all of its data.
+    pub fn load_from_reader(
+        mut reader: impl Read,
+    ) -> Result<AsepriteFile<'static>, LoadSpriteError> {
+        let mut data = Vec::new();
+        let _ = reader.read_to_end(&mut data)?;
+        Ok(AsepriteFile::load(&data)?.into_owned())
+    }
+    /// Convert into a file which does not borrow the input buffer. All
+    /// names and image data are copied.
+    ///
+    /// The owned file can be stored without keeping the input alive
+    /// and can be sent across threads.
+    pub fn into_owned(self) -> AsepriteFile<'static> {
+        AsepriteFile {
+            file: self.file.into_owned(),
+            layers: self.layers,
+            frames: self.frames,
+            tags: self.tags,
+            images: self.images.into_iter().map(Image::into_owned).collect(),
+            tilemaps: self.tilemaps.into_iter().map(Tilemap::into_owned).collect(),
+            diagnostics: self.diagnostics,
+        }
+    }
     /// Get size of the sprite (width, height)
     pub fn size(&self) -> (u16, u16) {
         (self.file.header.width, self.file.header.height)
@@ -234,6 +624,19 @@ impl AsepriteFile<'_> {
     pub fn layers(&self) -> &[Layer] {
         &self.layers
     }
+    /// Get the data which was skipped while loading in lenient mode
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    /// Create an [`Animation`] for the tag with the given name.
+    pub fn animation(&self, tag_name: &str) -> Result<Animation, LoadSpriteError> {
+        let tag = self
+            .tags
+            .iter()
+            .find(|tag| tag.name == tag_name)
+            .ok_or_else(|| LoadSpriteError::MissingTag(tag_name.to_owned()))?;
+        Animation::from_tag(tag, &self.frames)
+    }
     /// Get the image indices for a given tag and layer
     pub fn frames(&self) -> &[Frame] {
         &self.frames
@@ -272,9 +675,23 @@ impl AsepriteFile<'_> {
     ) -> Result<u64, LoadImageError> {
         self.render_frame(frame_index, target, &LayerSelection::Visible)?;
         let mut hasher = DefaultHasher::new();
-        let frame = &self.frames[frame_index];
+        let frame = self
+            .frames
+            .get(frame_index)
+            .ok_or(LoadImageError::FrameIndexOutOfRange(frame_index))?;
+        // Indexed cels look different with the palette of another frame
+        self.palette_index(frame_index).hash(&mut hasher);
         for cel in frame.cels.iter() {
-            (cel.image_index, cel.layer_index, cel.origin, cel.size).hash(&mut hasher);
+            (
+                cel.image_index,
+                cel.cel_type,
+                cel.layer_index,
+                cel.origin,
+                cel.size,
+                cel.opacity,
+                cel.z_index,
+            )
+                .hash(&mut hasher);
         }
         Ok(hasher.finish())
     }
@@ -283,113 +700,721 @@ impl AsepriteFile<'_> {
     /// on the layer selection into a buffer.
     ///
     /// The `target` buffer must be at least `width * height * 4` bytes.
-    /// Pixels are written as RGBA8.
+    /// Pixels are written as RGBA8. The layers are blended into the
+    /// current content of `target`, which allows drawing multiple sprites
+    /// into the same buffer. Use [`render_frame_with_options`](Self::render_frame_with_options)
+    /// to overwrite the `target` instead.
     pub fn render_frame(
         &self,
         frame_index: usize,
         target: &mut [u8],
         layers: &LayerSelection,
     ) -> Result<(), LoadImageError> {
-        let target_size =
-            usize::from(self.file.header.width) * usize::from(self.file.header.height) * 4;
+        let target = self.frame_target(target, PixelFormat::Rgba8)?;
+        self.composite_frame(
+            frame_index,
+            target,
+            layers,
+            &RenderOptions::default(),
+            CelPixels::Decode,
+            &mut Scratch::default(),
+            false,
+        )
+    }
 
-        if target.len() < target_size {
+    /// Render a frame for a given frame index using the given options.
+    ///
+    /// The `target` buffer must be at least `width * height` times the
+    /// [`bytes_per_pixel`](PixelFormat::bytes_per_pixel) of the format.
+    /// Formats other than [`PixelFormat::Rgba8`] are composited in RGBA8
+    /// in bands of a few rows and every band is converted into `target`
+    /// as soon as it is composited. The previous
+    /// content of `target` is overwritten for all formats.
+    pub fn render_frame_with_options(
+        &self,
+        frame_index: usize,
+        target: &mut [u8],
+        layers: &LayerSelection,
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        let target = self.frame_target(target, options.format)?;
+        self.render_frame_to_target(frame_index, target, layers, options)
+    }
+
+    /// Render the part of a frame which is inside of `rect`.
+    ///
+    /// The `target` buffer must be at least `rect.width * rect.height * 4`
+    /// bytes. Pixels are written as RGBA8 and the result matches the
+    /// pixels of `rect` in a frame rendered by [`render_frame`](Self::render_frame).
+    /// Pixels of `rect` which are outside of the sprite are cleared to
+    /// transparent. Cels which are outside of `rect` are not decoded at all.
+    pub fn render_frame_region(
+        &self,
+        frame_index: usize,
+        rect: Rect,
+        target: &mut [u8],
+        layers: &LayerSelection,
+    ) -> Result<(), LoadImageError> {
+        // The region has already been cleared by `region_target`
+        let target = region_target(rect, target)?;
+        self.composite_frame(
+            frame_index,
+            target,
+            layers,
+            &RenderOptions::default(),
+            CelPixels::Decode,
+            &mut Scratch::default(),
+            false,
+        )
+    }
+
+    /// Render a frame for a given frame index into a region of a larger
+    /// buffer.
+    ///
+    /// The region covered by the frame is overwritten. RGBA8 frames are
+    /// composited directly into the buffer. Other formats are composited
+    /// in RGBA8 in bands of a few rows which are converted into the
+    /// buffer right away.
+    pub fn render_frame_to_target(
+        &self,
+        frame_index: usize,
+        target: RenderTarget<'_>,
+        layers: &LayerSelection,
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        self.composite_frame(
+            frame_index,
+            target,
+            layers,
+            options,
+            CelPixels::Decode,
+            &mut Scratch::default(),
+            true,
+        )
+    }
+
+    /// Create a [`Renderer`] which caches the decoded images of this file.
+    pub fn renderer(&self) -> Renderer<'_> {
+        Renderer::new(self)
+    }
+
+    /// Wrap a buffer containing exactly one frame into a [`RenderTarget`].
+    fn frame_target<'t>(
+        &self,
+        target: &'t mut [u8],
+        format: PixelFormat,
+    ) -> Result<RenderTarget<'t>, LoadImageError> {
+        let (width, height) = self.size();
+        let pixel_count = usize::from(width) * usize::from(height);
+        if target.len() < format.buffer_size(pixel_count)? {
             return Err(LoadImageError::TargetBufferTooSmall);
         }
+        Ok(RenderTarget {
+            buffer: target,
+            stride: usize::from(width) * format.bytes_per_pixel(),
+            x: 0,
+            y: 0,
+        })
+    }
 
-        let frame = &self.frames[frame_index];
+    /// Composite the frame with the given index into `target`. RGBA8
+    /// frames are blended into the current content of `target` unless
+    /// `overwrite` is set. Other formats always overwrite `target`.
+    #[allow(clippy::too_many_arguments)]
+    fn composite_frame(
+        &self,
+        frame_index: usize,
+        target: RenderTarget<'_>,
+        layers: &LayerSelection,
+        options: &RenderOptions,
+        pixels: CelPixels<'_>,
+        scratch: &mut Scratch,
+        overwrite: bool,
+    ) -> Result<(), LoadImageError> {
+        let frame = self
+            .frames
+            .get(frame_index)
+            .ok_or(LoadImageError::FrameIndexOutOfRange(frame_index))?;
+        let format = options.format;
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let bounds = target_bounds(&target, self.size(), bytes_per_pixel);
+        if bounds.is_empty() {
+            return Ok(());
+        }
+        // Formats other than RGBA8 are composited in bands of rows which
+        // need all cels, so the cels are decoded up front.
+        let decoded: (Vec<Option<CachedImage>>, Vec<Option<CachedImage>>);
+        let pixels = match pixels {
+            CelPixels::Decode if format != PixelFormat::Rgba8 => {
+                let (mut images, mut tilemaps) = (
+                    vec![None; self.images.len()],
+                    vec![None; self.tilemaps.len()],
+                );
+                self.decode_frame_cels(
+                    frame_index,
+                    bounds,
+                    layers,
+                    options.recolor.as_ref(),
+                    &mut images,
+                    &mut tilemaps,
+                )?;
+                decoded = (images, tilemaps);
+                CelPixels::Cached {
+                    images: &decoded.0,
+                    tilemaps: &decoded.1,
+                }
+            }
+            pixels => pixels,
+        };
+        let mut context = RenderContext {
+            frame,
+            frame_index,
+            layers,
+            recolor: options.recolor.as_ref(),
+            pixels,
+            scratch,
+        };
+        let position = (target.x as isize, target.y as isize);
+        if format == PixelFormat::Rgba8 {
+            let mut target = TargetImage {
+                pixels: target.buffer,
+                stride: target.stride,
+                position,
+                bounds,
+            };
+            if overwrite {
+                target.clear()?;
+            }
+            return self.render_layers(&mut context, None, 0, &mut target);
+        }
+        // Every band is converted into `target` as soon as it has been
+        // composited. This keeps the RGBA8 pixels in the cache and does
+        // not need a RGBA8 buffer for the whole frame.
+        let mut band = std::mem::take(&mut context.scratch.band);
+        let row_size = bounds.width() * bytes_per_pixel;
+        let mut result = Ok(());
+        for y0 in (bounds.y0..bounds.y1).step_by(BAND_HEIGHT) {
+            let band_bounds = Bounds {
+                y0,
+                y1: bounds.y1.min(y0 + BAND_HEIGHT as i32),
+                ..bounds
+            };
+            band.clear();
+            band.resize(band_bounds.width() * band_bounds.height() * 4, 0);
+            result = self.render_layers(
+                &mut context,
+                None,
+                0,
+                &mut TargetImage::new(&mut band, band_bounds),
+            );
+            if result.is_err() {
+                break;
+            }
+            for (row, source) in band.chunks_exact(bounds.width() * 4).enumerate() {
+                let y = (band_bounds.y0 as isize + position.1) as usize + row;
+                let start = y * target.stride
+                    + (bounds.x0 as isize + position.0) as usize * bytes_per_pixel;
+                format.convert(source, &mut target.buffer[start..start + row_size]);
+            }
+        }
+        context.scratch.band = band;
+        result
+    }
 
-        for cel in frame.cels.iter() {
-            let Some(layer) = self.layers.get(cel.layer_index) else {
+    /// Decode the selected cels of a frame which are inside of `bounds`
+    /// into `images` and `tilemaps` (see [`CelPixels::Cached`]). Cels which
+    /// have already been decoded with the palette of the frame are kept.
+    fn decode_frame_cels(
+        &self,
+        frame_index: usize,
+        bounds: Bounds,
+        layers: &LayerSelection,
+        recolor: Option<&Recolor>,
+        images: &mut [Option<CachedImage>],
+        tilemaps: &mut [Option<CachedImage>],
+    ) -> Result<(), LoadImageError> {
+        let frame = self
+            .frames
+            .get(frame_index)
+            .ok_or(LoadImageError::FrameIndexOutOfRange(frame_index))?;
+        // Indexed images need to be decoded again if the palette changes
+        let palette_index = self.palette_index(frame_index);
+        for cel in &frame.cels {
+            if !layers.is_selected(cel.layer_index, &self.layers[cel.layer_index])
+                || cel.bounds().intersect(&bounds).is_empty()
+            {
                 continue;
+            }
+            let cache = match cel.cel_type {
+                CelType::Image => &mut *images,
+                CelType::Tilemap => &mut *tilemaps,
             };
-            if layer.layer_type == LayerType::Group || !layers.is_selected(cel.layer_index, layer) {
-                continue;
+            let entry = cache
+                .get_mut(cel.image_index)
+                .ok_or(LoadImageError::ImageIndexOutOfRange(cel.image_index))?;
+            if !matches!(entry, Some((index, _)) if *index == palette_index) {
+                *entry = Some((palette_index, self.decode_cel(cel, frame_index, recolor)?));
             }
+        }
+        Ok(())
+    }
 
-            let mut cel_target = vec![0; usize::from(cel.size.0) * usize::from(cel.size.1) * 4];
-            self.load_image(cel.image_index, &mut cel_target).unwrap();
-            let layer = &self.layers[cel.layer_index];
-
-            let blend_fn = blend_mode_to_blend_fn(layer.blend_mode);
+    /// Composite all children of the given `parent` layer into `target`.
+    /// `depth` is the number of groups containing the children.
+    ///
+    /// Group layers are rendered into a separate buffer first which is then
+    /// blended into `target` using the blend mode and opacity of the group.
+    fn render_layers(
+        &self,
+        context: &mut RenderContext<'_>,
+        parent: Option<usize>,
+        depth: usize,
+        target: &mut TargetImage<'_>,
+    ) -> Result<(), LoadImageError> {
+        let bounds = target.bounds;
+        let frame = context.frame;
+        if context.scratch.items.len() <= depth {
+            context.scratch.items.resize_with(depth + 1, Vec::new);
+            context.scratch.groups.resize_with(depth + 1, Vec::new);
+        }
 
-            for y in 0..cel.size.1 {
-                for x in 0..cel.size.0 {
-                    let Some(target_x) = x.checked_add_signed(cel.origin.0) else {
-                        continue;
-                    };
-                    if target_x >= self.file.header.width {
-                        continue;
+        let mut items = std::mem::take(&mut context.scratch.items[depth]);
+        self.render_items(frame, parent, context.layers, &mut items);
+        let result = items.iter().try_for_each(|&(_, _, _, item)| match item {
+            RenderItem::Group(layer_index) => {
+                // Without valid group values the children are drawn
+                // directly as if they were not part of a group.
+                let Some((blend_mode, opacity)) = self.group_blending(&self.layers[layer_index])
+                else {
+                    return self.render_layers(context, Some(layer_index), depth + 1, target);
+                };
+                let mut group_target = std::mem::take(&mut context.scratch.groups[depth]);
+                group_target.clear();
+                group_target.resize(bounds.width() * bounds.height() * 4, 0);
+                let result = self.render_layers(
+                    context,
+                    Some(layer_index),
+                    depth + 1,
+                    &mut TargetImage::new(&mut group_target, bounds),
+                );
+                let result = result.and_then(|_| {
+                    blend_image(
+                        target,
+                        &group_target,
+                        (bounds.width() as u16, bounds.height() as u16),
+                        (bounds.x0, bounds.y0),
+                        blend_mode,
+                        opacity,
+                    )
+                });
+                context.scratch.groups[depth] = group_target;
+                result
+            }
+            RenderItem::Cel(cel_index) => {
+                let cel = &frame.cels[cel_index];
+                // Cels outside of the rendered region don't need to be decoded
+                if cel.bounds().intersect(&bounds).is_empty() {
+                    return Ok(());
+                }
+                let layer = &self.layers[cel.layer_index];
+                let decoded;
+                let pixels = match context.pixels {
+                    CelPixels::Decode => {
+                        decoded = self.decode_cel(cel, context.frame_index, context.recolor)?;
+                        &decoded[..]
                     }
-                    let Some(target_y) = y.checked_add_signed(cel.origin.1) else {
-                        continue;
-                    };
-                    if target_y >= self.file.header.height {
-                        continue;
+                    CelPixels::Cached { images, tilemaps } => {
+                        let cache = match cel.cel_type {
+                            CelType::Image => images,
+                            CelType::Tilemap => tilemaps,
+                        };
+                        cache
+                            .get(cel.image_index)
+                            .and_then(Option::as_ref)
+                            .map(|(_, pixels)| &pixels[..])
+                            .ok_or(LoadImageError::ImageIndexOutOfRange(cel.image_index))?
                     }
+                };
+                // Aseprite multiplies the cel opacity with the layer opacity
+                let opacity = mul8(cel.opacity.into(), layer.opacity.into());
+                blend_image(
+                    target,
+                    pixels,
+                    cel.size,
+                    (cel.origin.0.into(), cel.origin.1.into()),
+                    layer.blend_mode,
+                    opacity,
+                )
+            }
+        });
+        context.scratch.items[depth] = items;
+        result
+    }
+
+    /// Get the blend mode and opacity used for compositing a group. They
+    /// are only valid if flag 2 of the file header is set. Otherwise
+    /// `None` is returned and the children of the group are drawn directly
+    /// into the target of the group.
+    fn group_blending(&self, layer: &Layer) -> Option<(BlendMode, u8)> {
+        (self.file.header.flags & 2 != 0).then_some((layer.blend_mode, layer.opacity))
+    }
+
+    /// Collect the selected children of the given `parent` layer into
+    /// `items` in the order in which they are composited.
+    fn render_items(
+        &self,
+        frame: &Frame,
+        parent: Option<usize>,
+        layers: &LayerSelection,
+        items: &mut Vec<(isize, i16, usize, RenderItem)>,
+    ) {
+        // Aseprite orders cels by `position + z_index` where `position` is
+        // the position of the layer among the rendered siblings. Ties are
+        // broken by the z-index. Groups are not affected by the z-index of
+        // cels and a cel can't be moved outside its parent group.
+        items.clear();
+        let siblings = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(layer_index, layer)| {
+                layer.parent == parent && layers.is_selected(*layer_index, layer)
+            });
+        for (position, (layer_index, layer)) in siblings.enumerate() {
+            if layer.layer_type == LayerType::Group {
+                let item = RenderItem::Group(layer_index);
+                items.push((position as isize, 0, items.len(), item));
+                continue;
+            }
+            for (cel_index, cel) in frame.cels.iter().enumerate() {
+                if cel.layer_index == layer_index {
+                    let order = position as isize + isize::from(cel.z_index);
+                    let item = RenderItem::Cel(cel_index);
+                    items.push((order, cel.z_index, items.len(), item));
+                }
+            }
+        }
+        // The insertion order makes the keys unique which makes the
+        // unstable sort, which does not allocate, behave like a stable one.
+        items.sort_unstable_by_key(|&(order, z_index, index, _)| (order, z_index, index));
+    }
+
+    /// Decode the image or tilemap of a cel into a new RGBA8 buffer using
+    /// the palette of the given frame.
+    fn decode_cel(
+        &self,
+        cel: &FrameCel,
+        frame_index: usize,
+        recolor: Option<&Recolor>,
+    ) -> Result<Vec<u8>, LoadImageError> {
+        let mut target = vec![0; usize::from(cel.size.0) * usize::from(cel.size.1) * 4];
+        let palette = self.indexed_palette(frame_index, cel.layer_index);
+        let format = PixelFormat::Rgba8;
+        match cel.cel_type {
+            CelType::Image => {
+                self.decode_image(cel.image_index, palette, recolor, &mut target, format)
+            }
+            CelType::Tilemap => {
+                self.decode_tilemap(cel.image_index, palette, recolor, &mut target, format)
+            }
+        }?;
+        Ok(target)
+    }
+
+    /// Get the palette for decoding the indexed pixels of a cel in the
+    /// given frame and layer.
+    fn indexed_palette(
+        &self,
+        frame_index: usize,
+        layer_index: usize,
+    ) -> Option<IndexedPalette<'_>> {
+        // The transparent index is drawn opaque in background layers
+        let background = self
+            .layers
+            .get(layer_index)
+            .is_some_and(|layer| layer.background);
+        Some(IndexedPalette {
+            palette: self.palette(frame_index)?,
+            transparent_index: (!background).then_some(self.file.header.transparent_index),
+        })
+    }
+
+    /// Get the palette which is used by the frame with the given index.
+    ///
+    /// Aseprite allows changing the palette in later frames. Every frame
+    /// uses the palette of the closest preceding frame which contains
+    /// a palette chunk. This is `None` for files which are not indexed.
+    pub fn palette(&self, frame_index: usize) -> Option<&Palette> {
+        let palettes = &self.file.palettes;
+        let index = palettes.partition_point(|(first_frame, _)| *first_frame <= frame_index);
+        match palettes.get(index.saturating_sub(1)) {
+            Some((_, palette)) => Some(palette),
+            // Files which have not been parsed might only contain
+            // a single palette.
+            None => self.file.palette.as_ref(),
+        }
+    }
+
+    /// Index into [`File::palettes`] of the palette used by the given frame
+    fn palette_index(&self, frame_index: usize) -> usize {
+        self.file
+            .palettes
+            .partition_point(|(first_frame, _)| *first_frame <= frame_index)
+            .saturating_sub(1)
+    }
+
+    /// Get the palette for decoding the given image or tilemap. This is
+    /// the palette of the first frame and the layer of the first cel
+    /// using it.
+    fn image_palette(&self, cel_type: CelType, index: usize) -> Option<IndexedPalette<'_>> {
+        let (frame_index, layer_index) = self
+            .frames
+            .iter()
+            .enumerate()
+            .find_map(|(frame_index, frame)| {
+                frame
+                    .cels
+                    .iter()
+                    .find(|cel| cel.cel_type == cel_type && cel.image_index == index)
+                    .map(|cel| (frame_index, cel.layer_index))
+            })
+            .unwrap_or((0, usize::MAX));
+        self.indexed_palette(frame_index, layer_index)
+    }
+
+    /// Get image loader for a given image index
+    pub fn load_image(&self, index: usize, target: &mut [u8]) -> Result<(), LoadImageError> {
+        self.load_image_with_options(index, target, &RenderOptions::default())
+    }
+
+    /// Load the image with the given index into `target` using the given
+    /// options. The pixels are converted to the pixel format while they
+    /// are decoded.
+    ///
+    /// Indexed images use the palette of the first frame containing them.
+    pub fn load_image_with_options(
+        &self,
+        index: usize,
+        target: &mut [u8],
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        let palette = self.image_palette(CelType::Image, index);
+        let recolor = options.recolor.as_ref();
+        self.decode_image(index, palette, recolor, target, options.format)
+    }
+
+    /// Decode the image with the given index using the given palette.
+    fn decode_image(
+        &self,
+        index: usize,
+        palette: Option<IndexedPalette<'_>>,
+        recolor: Option<&Recolor>,
+        target: &mut [u8],
+        format: PixelFormat,
+    ) -> Result<(), LoadImageError> {
+        let image = self
+            .images
+            .get(index)
+            .ok_or(LoadImageError::ImageIndexOutOfRange(index))?;
+        let pixel_count = usize::from(image.width) * usize::from(image.height);
+        self.decode_pixels(
+            &image.data,
+            image.compressed,
+            pixel_count,
+            palette,
+            recolor,
+            target,
+            format,
+        )
+    }
+
+    /// Render the tilemap with the given index into `target`.
+    ///
+    /// The `target` buffer must be at least the size of the tilemap in
+    /// pixels times 4. Pixels are written as RGBA8.
+    pub fn load_tilemap(&self, index: usize, target: &mut [u8]) -> Result<(), LoadImageError> {
+        self.load_tilemap_with_options(index, target, &RenderOptions::default())
+    }
+
+    /// Render the tilemap with the given index into `target` using the
+    /// given options.
+    ///
+    /// Indexed tilesets use the palette of the first frame containing
+    /// the tilemap.
+    pub fn load_tilemap_with_options(
+        &self,
+        index: usize,
+        target: &mut [u8],
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        let palette = self.image_palette(CelType::Tilemap, index);
+        let recolor = options.recolor.as_ref();
+        self.decode_tilemap(index, palette, recolor, target, options.format)
+    }
+
+    /// Render the tilemap with the given index using the given palette.
+    fn decode_tilemap(
+        &self,
+        index: usize,
+        palette: Option<IndexedPalette<'_>>,
+        recolor: Option<&Recolor>,
+        target: &mut [u8],
+        format: PixelFormat,
+    ) -> Result<(), LoadImageError> {
+        let mut empty = [0; 16];
+        let empty = &mut empty[..format.bytes_per_pixel()];
+        format.write_pixel([0; 4], empty);
+        self.draw_tilemap(index, target, empty, |data, pixel_count, target| {
+            self.decode_pixels(data, true, pixel_count, palette, recolor, target, format)
+        })
+    }
 
-                    let target_index = usize::from(target_y) * usize::from(self.file.header.width)
-                        + usize::from(target_x);
-                    let cel_index = usize::from(y) * usize::from(cel.size.0) + usize::from(x);
+    /// Draw the tiles of the tilemap with the given index into `target`.
+    ///
+    /// `decode_tileset` decodes the compressed pixels of the tileset into
+    /// pixels which have the same size as `empty`. Pixels which are not
+    /// covered by a tile are set to `empty`.
+    fn draw_tilemap(
+        &self,
+        index: usize,
+        target: &mut [u8],
+        empty: &[u8],
+        decode_tileset: impl FnOnce(&[u8], usize, &mut [u8]) -> Result<(), LoadImageError>,
+    ) -> Result<(), LoadImageError> {
+        let bytes_per_pixel = empty.len();
+        let tilemap = self
+            .tilemaps
+            .get(index)
+            .ok_or(LoadImageError::ImageIndexOutOfRange(index))?;
+        let tileset = self
+            .file
+            .tilesets
+            .get(tilemap.tileset_index)
+            .ok_or(LoadImageError::MissingTileset)?;
+        let tiles = tileset
+            .tiles
+            .as_ref()
+            .ok_or(LoadImageError::MissingTileset)?;
+        let tile_width = usize::from(tileset.width);
+        let tile_height = usize::from(tileset.height);
+        let tile_count = usize::try_from(tileset.number_of_tiles)
+            .map_err(|_| LoadImageError::InvalidImageData)?;
+        let width = usize::from(tilemap.size.0) * tile_width;
+        let height = usize::from(tilemap.size.1) * tile_height;
+        let target_size = (width * height)
+            .checked_mul(bytes_per_pixel)
+            .ok_or(LoadImageError::TargetBufferTooSmall)?;
+        if target.len() < target_size {
+            return Err(LoadImageError::TargetBufferTooSmall);
+        }
+        let target = &mut target[..target_size];
+        for pixel in target.chunks_exact_mut(bytes_per_pixel) {
+            pixel.copy_from_slice(empty);
+        }
 
-                    let cel_pixel: &[u8] = &cel_target[cel_index * 4..cel_index * 4 + 4];
-                    let target_pixel: &mut [u8] =
-                        &mut target[target_index * 4..target_index * 4 + 4];
+        // The number of tiles is read from the file and might be bogus
+        let tileset_pixel_count = (tile_width * tile_height)
+            .checked_mul(tile_count)
+            .filter(|count| count.checked_mul(bytes_per_pixel).is_some())
+            .ok_or(LoadImageError::InvalidImageData)?;
+        let mut tileset_image = vec![0; tileset_pixel_count * bytes_per_pixel];
+        decode_tileset(&tiles.data, tileset_pixel_count, &mut tileset_image)?;
 
-                    let back = Color::from(&*target_pixel);
-                    let front = Color::from(cel_pixel);
-                    let out = blend_fn(back, front, layer.opacity);
+        let bytes_per_tile = match tilemap.bits_per_tile {
+            8 => 1,
+            16 => 2,
+            32 => 4,
+            _ => return Err(LoadImageError::InvalidImageData),
+        };
+        let mut tile_data =
+            vec![0; usize::from(tilemap.size.0) * usize::from(tilemap.size.1) * bytes_per_tile];
+        decompress(&tilemap.data, &mut tile_data)?;
 
-                    target_pixel[0] = out.r;
-                    target_pixel[1] = out.g;
-                    target_pixel[2] = out.b;
-                    target_pixel[3] = out.a;
+        let tile_0_empty = tileset.flags.contains(TilesetFlags::TILE_0_EMPTY);
+        for (i, tile) in tile_data.chunks_exact(bytes_per_tile).enumerate() {
+            let tile = tile
+                .iter()
+                .rev()
+                .fold(0u32, |acc, &byte| (acc << 8) | u32::from(byte));
+            let tile_id = (tile & tilemap.bitmask_tile_id) as usize;
+            if (tile_0_empty && tile_id == 0) || tile_id >= tile_count {
+                continue;
+            }
+            let x_flip = tile & tilemap.bitmask_x_flip != 0;
+            let y_flip = tile & tilemap.bitmask_y_flip != 0;
+            // Diagonal flips are only possible for square tiles
+            let d_flip = tile & tilemap.bitmask_diagonal_flip != 0 && tile_width == tile_height;
+            let tile_x = i % usize::from(tilemap.size.0) * tile_width;
+            let tile_y = i / usize::from(tilemap.size.0) * tile_height;
+            let tile_offset = tile_id * tile_width * tile_height;
+            for y in 0..tile_height {
+                for x in 0..tile_width {
+                    let mut src_x = if x_flip { tile_width - 1 - x } else { x };
+                    let mut src_y = if y_flip { tile_height - 1 - y } else { y };
+                    if d_flip {
+                        std::mem::swap(&mut src_x, &mut src_y);
+                    }
+                    let src_index = (tile_offset + src_y * tile_width + src_x) * bytes_per_pixel;
+                    let dst_index = ((tile_y + y) * width + tile_x + x) * bytes_per_pixel;
+                    target[dst_index..dst_index + bytes_per_pixel]
+                        .copy_from_slice(&tileset_image[src_index..src_index + bytes_per_pixel]);
                 }
             }
         }
         Ok(())
     }
 
-    /// Get image loader for a given image index
-    pub fn load_image(&self, index: usize, target: &mut [u8]) -> Result<(), LoadImageError> {
-        let image = &self.images[index];
-        let target_size = usize::from(image.width) * usize::from(image.height) * 4;
+    /// Decode `pixel_count` pixels stored in the color depth of the file
+    /// into pixels of the given format. Indexed pixels use the given palette
+    /// unless it is replaced by `recolor`.
+    #[allow(clippy::too_many_arguments)]
+    fn decode_pixels(
+        &self,
+        data: &[u8],
+        compressed: bool,
+        pixel_count: usize,
+        palette: Option<IndexedPalette<'_>>,
+        recolor: Option<&Recolor>,
+        target: &mut [u8],
+        format: PixelFormat,
+    ) -> Result<(), LoadImageError> {
+        let target_size = format.buffer_size(pixel_count)?;
         if target.len() < target_size {
             return Err(LoadImageError::TargetBufferTooSmall);
         }
         let target = &mut target[..target_size];
-        match (self.file.header.color_depth, image.compressed) {
-            (ColorDepth::Rgba, false) => target.copy_from_slice(image.data),
-            (ColorDepth::Rgba, true) => decompress(image.data, target)?,
+        match (self.file.header.color_depth, compressed) {
+            (ColorDepth::Rgba, false) => {
+                if data.len() != pixel_count * 4 {
+                    return Err(LoadImageError::InvalidImageData);
+                }
+                rgba_to_format(data, recolor, format, target);
+            }
+            (ColorDepth::Rgba, true) if format == PixelFormat::Rgba8 && recolor.is_none() => {
+                decompress(data, target)?
+            }
+            (ColorDepth::Rgba, true) => {
+                let mut buf = vec![0u8; pixel_count * 4];
+                decompress(data, &mut buf)?;
+                rgba_to_format(&buf, recolor, format, target);
+            }
             (ColorDepth::Grayscale, false) => {
-                grayscale_to_rgba(image.data, target)?;
+                grayscale_to_format(data, recolor, format, target)?;
             }
             (ColorDepth::Grayscale, true) => {
-                let mut buf = vec![0u8; usize::from(image.width) * usize::from(image.height) * 2];
-                decompress(image.data, &mut buf)?;
-                grayscale_to_rgba(&buf, target)?;
+                let mut buf = vec![0u8; pixel_count * 2];
+                decompress(data, &mut buf)?;
+                grayscale_to_format(&buf, recolor, format, target)?;
             }
             (ColorDepth::Indexed, false) => {
-                indexed_to_rgba(
-                    image.data,
-                    self.file
-                        .palette
-                        .as_ref()
-                        .ok_or(LoadImageError::MissingPalette)?,
-                    target,
-                )?;
+                let palette = palette.ok_or(LoadImageError::MissingPalette)?;
+                indexed_to_format(data, palette, recolor, format, target)?;
             }
             (ColorDepth::Indexed, true) => {
-                let mut buf = vec![0u8; usize::from(image.width) * usize::from(image.height)];
-                decompress(image.data, &mut buf)?;
-                indexed_to_rgba(
-                    &buf,
-                    self.file
-                        .palette
-                        .as_ref()
-                        .ok_or(LoadImageError::MissingPalette)?,
-                    target,
-                )?;
+                let mut buf = vec![0u8; pixel_count];
+                decompress(data, &mut buf)?;
+                let palette = palette.ok_or(LoadImageError::MissingPalette)?;
+                indexed_to_format(&buf, palette, recolor, format, target)?;
             }
             (ColorDepth::Unknown(_), _) => return Err(LoadImageError::UnsupportedColorDepth),
         }
@@ -407,10 +1432,38 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum LoadSpriteError {
     /// Parsing the binary `.aseprite` content failed.
-    #[error("parsing failed {message}")]
-    Parse {
-        /// Detailed parse failure description.
-        message: String,
+    #[error("parsing failed")]
+    Parse(#[from] ParseError),
+    /// Reading the file failed.
+    #[error("reading failed")]
+    Io(#[from] std::io::Error),
+    /// The tile data of a tileset does not match the number and size
+    /// of its tiles.
+    #[error("invalid tileset: {0}")]
+    InvalidTileset(u32),
+    /// A tilemap cel refers to a tileset which does not exist.
+    #[error("missing tileset for tilemap at frame {frame} layer {layer}")]
+    MissingTileset {
+        /// Frame index
+        frame: usize,
+        /// Layer index in the file
+        layer: usize,
+    },
+    /// A linked cel refers to a frame without a cel on the same layer.
+    #[error("invalid linked cel at frame {frame} layer {layer}")]
+    InvalidLinkedCel {
+        /// Frame index
+        frame: usize,
+        /// Layer index in the file
+        layer: usize,
+    },
+    /// A cel is of an unknown type or its data does not match its size.
+    #[error("invalid cel at frame {frame} layer {layer}")]
+    InvalidCel {
+        /// Frame index
+        frame: usize,
+        /// Layer index in the file
+        layer: usize,
     },
     /// A named tag lookup failed.
     #[error("missing tag: {0}")]
@@ -421,6 +1474,20 @@ pub enum LoadSpriteError {
     /// A frame index was outside available frame bounds.
     #[error("frame index out of range: {0}")]
     FrameIndexOutOfRange(usize),
+    /// The first frame of a tag comes after its last frame.
+    #[error("invalid frame range of tag: {0}")]
+    InvalidTagRange(String),
+}
+
+/// Data which was skipped while loading a file in lenient mode
+#[derive(Error, Debug)]
+pub enum Diagnostic {
+    /// A chunk or frame of the file could not be parsed.
+    #[error("skipped invalid data: {0}")]
+    Parse(ParseError),
+    /// A tileset or cel could not be loaded.
+    #[error("skipped: {0}")]
+    Load(LoadSpriteError),
 }
 
 /// Errors that can occur while decoding image pixel data.
@@ -433,6 +1500,10 @@ pub enum LoadImageError {
     /// Indexed image decoding requires a palette, but none was present.
     #[error("missing palette")]
     MissingPalette,
+    /// Tilemap decoding requires the tiles of the tileset, but the tileset
+    /// does not contain any tiles (e.g. because it is stored in an external file).
+    #[error("missing tileset")]
+    MissingTileset,
     /// The file uses an unsupported color depth.
     #[error("unsupported color depth")]
     UnsupportedColorDepth,
@@ -442,6 +1513,85 @@ pub enum LoadImageError {
     /// Pixel data length did not match the expected format.
     #[error("invalid image data")]
     InvalidImageData,
+    /// A frame index was outside available frame bounds.
+    #[error("frame index out of range: {0}")]
+    FrameIndexOutOfRange(usize),
+    /// An image or tilemap index was outside available bounds.
+    #[error("image index out of range: {0}")]
+    ImageIndexOutOfRange(usize),
+    /// A layer uses a blend mode which is not supported.
+    #[error("unsupported blend mode: {0:?}")]
+    UnsupportedBlendMode(BlendMode),
+    /// The layer with the given index can't be rendered to palette
+    /// indices because it uses a blend mode other than
+    /// [`BlendMode::Normal`] or is partially transparent.
+    #[error("layer {0} can't be rendered to palette indices")]
+    UnsupportedIndexedBlending(usize),
+    /// The size of the sprite sheet does not fit into an `u32` or the
+    /// pixels do not fit into memory.
+    #[error("sprite sheet too large")]
+    SheetTooLarge,
+}
+
+/// Wrap a buffer for the pixels of `rect` into a [`RenderTarget`] which
+/// is positioned so that only the pixels of `rect` are rendered.
+fn region_target(rect: Rect, target: &mut [u8]) -> Result<RenderTarget<'_>, LoadImageError> {
+    let size = usize::try_from(rect.width)
+        .ok()
+        .zip(usize::try_from(rect.height).ok())
+        .and_then(|(width, height)| width.checked_mul(height)?.checked_mul(4))
+        .ok_or(LoadImageError::TargetBufferTooSmall)?;
+    if target.len() < size {
+        return Err(LoadImageError::TargetBufferTooSmall);
+    }
+    // Pixels of the region outside of the frame are never written
+    // when compositing so the whole region is cleared up front.
+    target[..size].fill(0);
+    // Frames are at most `u16::MAX` pixels wide so saturating the
+    // position still places the frame outside of the buffer.
+    let position = |value: u32| -i32::try_from(value).unwrap_or(i32::MAX);
+    Ok(RenderTarget {
+        buffer: &mut target[..size],
+        stride: (rect.width as usize).saturating_mul(4),
+        x: position(rect.x),
+        y: position(rect.y),
+    })
+}
+
+/// The pixels of a frame with the given size which are inside the
+/// buffer of the render target
+fn target_bounds(target: &RenderTarget<'_>, size: (u16, u16), bytes_per_pixel: usize) -> Bounds {
+    let buffer_width = i32::try_from(target.stride / bytes_per_pixel).unwrap_or(i32::MAX);
+    let mut bounds = Bounds::from_size(size).intersect(&Bounds {
+        x0: target.x.saturating_neg(),
+        y0: target.y.saturating_neg(),
+        x1: buffer_width.saturating_sub(target.x),
+        y1: i32::MAX,
+    });
+    if bounds.is_empty() {
+        return bounds;
+    }
+    // Only rows which fit into the buffer up to the last visible pixel
+    // are rendered.
+    let row_end = (bounds.x1 as isize + target.x as isize) as usize * bytes_per_pixel;
+    let rows = match target.buffer.len().checked_sub(row_end) {
+        Some(remainder) => remainder / target.stride + 1,
+        None => 0,
+    };
+    let buffer_height = i32::try_from(rows).unwrap_or(i32::MAX);
+    bounds.y1 = bounds.y1.min(buffer_height.saturating_sub(target.y));
+    bounds
+}
+
+/// Check if `data` can contain `size` bytes of image data. Zlib can't
+/// compress data by more than a factor of 1032. This allows rejecting
+/// bogus image sizes before allocating memory for them.
+fn is_valid_data_size(data: &[u8], compressed: bool, size: usize) -> bool {
+    if compressed {
+        size <= data.len().saturating_mul(1032)
+    } else {
+        data.len() == size
+    }
 }
 
 /// Decompress zlib-compressed image bytes into `target`.
@@ -456,33 +1606,76 @@ pub fn decompress(data: &[u8], target: &mut [u8]) -> Result<(), LoadImageError>
     }
 }
 
-fn grayscale_to_rgba(source: &[u8], target: &mut [u8]) -> Result<(), LoadImageError> {
-    if target.len() != source.len() * 2 {
+fn rgba_to_format(
+    source: &[u8],
+    recolor: Option<&Recolor>,
+    format: PixelFormat,
+    target: &mut [u8],
+) {
+    let Some(recolor) = recolor.filter(|recolor| recolor.palette().is_none()) else {
+        format.convert(source, target);
+        return;
+    };
+    for (pixel, chunk) in target
+        .chunks_exact_mut(format.bytes_per_pixel())
+        .zip(source.chunks_exact(4))
+    {
+        let color = recolor.map([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        format.write_pixel(color, pixel);
+    }
+}
+
+fn grayscale_to_format(
+    source: &[u8],
+    recolor: Option<&Recolor>,
+    format: PixelFormat,
+    target: &mut [u8],
+) -> Result<(), LoadImageError> {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    if target.len() != source.len() / 2 * bytes_per_pixel || source.len() % 2 != 0 {
         return Err(LoadImageError::InvalidImageData);
     }
-    for (i, chunk) in source.chunks(2).enumerate() {
-        target[i * 4] = chunk[0];
-        target[i * 4 + 1] = chunk[0];
-        target[i * 4 + 2] = chunk[0];
-        target[i * 4 + 3] = chunk[1];
+    for (pixel, chunk) in target
+        .chunks_exact_mut(bytes_per_pixel)
+        .zip(source.chunks_exact(2))
+    {
+        let mut color = [chunk[0], chunk[0], chunk[0], chunk[1]];
+        if let Some(recolor) = recolor {
+            color = recolor.map(color);
+        }
+        format.write_pixel(color, pixel);
     }
     Ok(())
 }
 
-fn indexed_to_rgba(
+fn indexed_to_format(
     source: &[u8],
-    palette: &Palette,
+    palette: IndexedPalette<'_>,
+    recolor: Option<&Recolor>,
+    format: PixelFormat,
     target: &mut [u8],
 ) -> Result<(), LoadImageError> {
-    if target.len() != source.len() * 4 {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    if target.len() != source.len() * bytes_per_pixel {
         return Err(LoadImageError::InvalidImageData);
     }
-    for (i, px) in source.iter().enumerate() {
-        let color = palette.colors[usize::from(*px)];
-        target[i * 4] = color.red;
-        target[i * 4 + 1] = color.green;
-        target[i * 4 + 2] = color.blue;
-        target[i * 4 + 3] = color.alpha;
+    let colors = &recolor
+        .and_then(Recolor::palette)
+        .unwrap_or(palette.palette)
+        .colors;
+    for (pixel, px) in target.chunks_exact_mut(bytes_per_pixel).zip(source) {
+        if Some(*px) == palette.transparent_index {
+            format.write_pixel([0; 4], pixel);
+            continue;
+        }
+        let color = colors
+            .get(usize::from(*px))
+            .ok_or(LoadImageError::InvalidImageData)?;
+        let mut color = [color.red, color.green, color.blue, color.alpha];
+        if let Some(recolor) = recolor {
+            color = recolor.map(color);
+        }
+        format.write_pixel(color, pixel);
     }
     Ok(())
 }
@@ -583,6 +1776,253 @@ fn test_visible_layers_single_differs_from_all() {
     );
 }
 
+#[test]
+fn test_cel_opacity() {
+    let data = std::fs::read("./tests/cel_opacity.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let (width, height) = file.size();
+    let mut buf = vec![0u8; usize::from(width) * usize::from(height) * 4];
+
+    // Cel opacity 128 on a layer with opacity 255
+    assert_eq!(file.frames()[0].cels[0].opacity, 128);
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [255, 0, 0, 128, 255, 0, 0, 128]);
+
+    // Cel opacity 128 on a layer with opacity 128
+    buf.fill(0);
+    file.render_frame(1, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [255, 0, 0, 64, 255, 0, 0, 64]);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_combined_frame_image_hash_cel_opacity() {
+    let data = std::fs::read("./tests/cel_opacity.aseprite").unwrap();
+    let mut file = AsepriteFile::load(&data).unwrap();
+    let (width, height) = file.size();
+    let mut buf = vec![0u8; usize::from(width) * usize::from(height) * 4];
+    let hash = file.combined_frame_image(0, &mut buf).unwrap();
+    // Only the cel opacity differs
+    file.frames[0].cels[0].opacity = 255;
+    buf.fill(0);
+    assert_ne!(file.combined_frame_image(0, &mut buf).unwrap(), hash);
+}
+
+#[test]
+fn test_groups() {
+    let data = std::fs::read("./tests/groups.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let parents: Vec<_> = file.layers().iter().map(|l| l.parent).collect();
+    assert_eq!(
+        parents,
+        [None, None, Some(1), Some(2), Some(2), None, Some(5)]
+    );
+
+    let (width, height) = file.size();
+    let mut buf = vec![0u8; usize::from(width) * usize::from(height) * 4];
+
+    // The "body" group is blended as a whole with an opacity of 128 and
+    // the hidden group also hides its visible child layer.
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [0, 128, 127, 255, 0, 0, 255, 255]);
+
+    buf.fill(0);
+    file.render_frame(0, &mut buf, &LayerSelection::All)
+        .unwrap();
+    assert_eq!(buf, [255, 255, 255, 255, 255, 255, 255, 255]);
+
+    // Groups do not need to be part of the mask
+    buf.fill(0);
+    let sel = file.select_layers_by_name(&["sleeve"]);
+    file.render_frame(0, &mut buf, &sel).unwrap();
+    assert_eq!(buf, [255, 0, 0, 128, 0, 0, 0, 0]);
+
+    // Without the header flag for valid group values the opacity of
+    // the "body" group is ignored.
+    let mut data = data;
+    data[14] = 1;
+    let file = AsepriteFile::load(&data).unwrap();
+    assert_eq!(file.layers()[1].opacity, 128);
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [0, 255, 0, 255, 0, 0, 255, 255]);
+}
+
+#[test]
+fn test_group_children_without_group_flag() {
+    use crate::builder::{Cel, LayerOptions, SpriteBuilder};
+
+    let mut sprite = SpriteBuilder::new(1, 1, ColorDepth::Rgba);
+    let background = sprite
+        .add_layer("Background", &LayerOptions::default())
+        .unwrap();
+    let group = sprite.add_group("Group", &LayerOptions::default()).unwrap();
+    let child = sprite
+        .add_layer(
+            "Child",
+            &LayerOptions {
+                parent: Some(group),
+                blend_mode: BlendMode::Multiply,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let frame = sprite.add_frame(100);
+    sprite
+        .add_cel(frame, background, Cel::new(1, 1, vec![200, 100, 50, 255]))
+        .unwrap();
+    sprite
+        .add_cel(frame, child, Cel::new(1, 1, vec![128, 128, 128, 255]))
+        .unwrap();
+    let data = sprite.build().unwrap();
+    let mut file = AsepriteFile::load(&data).unwrap();
+
+    // With valid group values the child is blended into the empty group
+    let mut target = [0; 4];
+    file.render_frame(0, &mut target, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(target, [128, 128, 128, 255]);
+
+    // Otherwise the child is multiplied with the layer below the group
+    file.file.header.flags = 1;
+    let mut target = [0; 4];
+    file.render_frame(0, &mut target, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(target, [100, 50, 25, 255]);
+}
+
+#[test]
+fn test_render_frame_blends_into_target() {
+    let data = std::fs::read("./tests/groups.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let layers = file.select_layers_by_name(&["sleeve"]);
+    let mut renderer = file.renderer();
+    let blue = [0, 0, 255, 255];
+
+    // `render_frame` blends into the current content of the target
+    let mut expected = blue.repeat(2);
+    expected[..4].copy_from_slice(&[128, 0, 127, 255]);
+    let mut target = blue.repeat(2);
+    file.render_frame(0, &mut target, &layers).unwrap();
+    assert_eq!(target, expected);
+    let mut target = blue.repeat(2);
+    renderer.render_frame(0, &mut target, &layers).unwrap();
+    assert_eq!(target, expected);
+
+    // The functions taking options overwrite the target
+    let options = RenderOptions::default();
+    let expected = [255, 0, 0, 128, 0, 0, 0, 0];
+    let mut target = blue.repeat(2);
+    file.render_frame_with_options(0, &mut target, &layers, &options)
+        .unwrap();
+    assert_eq!(target, expected);
+    let mut target = blue.repeat(2);
+    renderer
+        .render_frame_with_options(0, &mut target, &layers, &options)
+        .unwrap();
+    assert_eq!(target, expected);
+}
+
+#[test]
+fn test_tilemap() {
+    let data = std::fs::read("./tests/tilemap.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    assert_eq!(file.layers()[0].layer_type, LayerType::Tilemap);
+    let cel = file.frames()[0].cels[0];
+    assert_eq!(cel.cel_type, CelType::Tilemap);
+    assert_eq!(cel.size, (10, 2));
+
+    let (width, height) = file.size();
+    let mut buf = vec![0u8; usize::from(width) * usize::from(height) * 4];
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+
+    const R: [u8; 4] = [255, 0, 0, 255];
+    const G: [u8; 4] = [0, 255, 0, 255];
+    const B: [u8; 4] = [0, 0, 255, 255];
+    const W: [u8; 4] = [255, 255, 255, 255];
+    const T: [u8; 4] = [0, 0, 0, 0];
+    // Tiles: plain, x-flip, y-flip, diagonal flip, empty
+    let expected = [
+        [R, G, G, R, B, W, R, B, T, T].concat(),
+        [B, W, W, B, R, G, G, W, T, T].concat(),
+    ]
+    .concat();
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn test_z_index() {
+    let data = std::fs::read("./tests/z_index.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    assert_eq!(file.frames()[1].cels[1].z_index, -1);
+    let mut buf = [0u8; 4];
+
+    // No z-index: the sword is drawn above the body
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [0, 255, 0, 255]);
+
+    // The sword has a z-index of -1 and is drawn behind the body
+    file.render_frame(1, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [255, 0, 0, 255]);
+
+    // The body has a z-index of 1 and is drawn above the sword
+    file.render_frame(2, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_z_index_hidden_layer() {
+    use crate::builder::{Cel, LayerOptions, SpriteBuilder};
+
+    let mut sprite = SpriteBuilder::new(1, 1, ColorDepth::Rgba);
+    let bottom = sprite
+        .add_layer("Bottom", &LayerOptions::default())
+        .unwrap();
+    let hidden = sprite
+        .add_layer(
+            "Hidden",
+            &LayerOptions {
+                visible: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let top = sprite.add_layer("Top", &LayerOptions::default()).unwrap();
+    let frame = sprite.add_frame(100);
+    let cel = Cel {
+        z_index: 1,
+        ..Cel::new(1, 1, vec![255, 0, 0, 255])
+    };
+    sprite.add_cel(frame, bottom, cel).unwrap();
+    sprite
+        .add_cel(frame, hidden, Cel::new(1, 1, vec![0, 255, 0, 255]))
+        .unwrap();
+    sprite
+        .add_cel(frame, top, Cel::new(1, 1, vec![0, 0, 255, 255]))
+        .unwrap();
+    let data = sprite.build().unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let mut buf = [0u8; 4];
+
+    // The hidden layer is skipped so the z-index moves the bottom cel
+    // above the top cel
+    file.render_frame(0, &mut buf, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(buf, [255, 0, 0, 255]);
+
+    // With the hidden layer rendered the bottom cel only ties with it
+    file.render_frame(0, &mut buf, &LayerSelection::All)
+        .unwrap();
+    assert_eq!(buf, [0, 0, 255, 255]);
+}
+
 /// https://github.com/bikeshedder/aseprite-loader/issues/4
 #[test]
 fn test_issue_4_1() {
@@ -609,3 +2049,479 @@ fn test_issue_4_2() {
             .unwrap();
     }
 }
+
+#[test]
+fn test_malformed() {
+    use itertools::Itertools;
+    let mut inputs = Vec::new();
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) == Some("aseprite") {
+            inputs.push(std::fs::read(path).unwrap());
+        }
+    }
+    // Only the used part of the header, the frame and chunk headers, the
+    // start of every chunk, which contains sizes and name lengths, and a
+    // sample of the other bytes are mutated. Exhaustive mutation is left to `fuzz/`.
+    let offsets = |input: &[u8]| {
+        let read = |offset: usize, size: usize| {
+            input[offset..offset + size]
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | usize::from(byte))
+        };
+        let mut offsets: Vec<usize> = (0..44).chain((44..input.len()).step_by(97)).collect();
+        let mut frame = 128;
+        while frame + 16 <= input.len() {
+            let frame_end = (frame + read(frame, 4)).min(input.len());
+            let chunks = match read(frame + 12, 4) {
+                0 => read(frame + 6, 2),
+                chunks => chunks,
+            };
+            offsets.extend(frame..frame + 16);
+            let mut chunk = frame + 16;
+            for _ in 0..chunks {
+                if chunk + 6 > frame_end {
+                    break;
+                }
+                offsets.extend(chunk..(chunk + 30).min(frame_end));
+                chunk += read(chunk, 4).max(6);
+            }
+            frame = frame_end.max(frame + 16);
+        }
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    };
+    for input in inputs {
+        for i in offsets(&input) {
+            let mut corrupted = input.clone();
+            corrupted[i] ^= 0xFF;
+            for (data, strict) in [&input[..i], &corrupted[..]]
+                .into_iter()
+                .cartesian_product([true, false])
+            {
+                let options = LoadOptions { strict };
+                let Ok(file) = AsepriteFile::load_with_options(data, &options) else {
+                    continue;
+                };
+                let (width, height) = file.size();
+                // Corrupted sprite sizes would make this test slow
+                if width > 64 || height > 64 {
+                    continue;
+                }
+                let mut buf = vec![0; usize::from(width) * usize::from(height) * 4];
+                for idx in 0..=file.frames().len() {
+                    let _ = file.render_frame(idx, &mut buf, &LayerSelection::All);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    use std::error::Error;
+    let mut data = std::fs::read("./tests/layers.aseprite").unwrap();
+    data[144..148].copy_from_slice(&3u32.to_le_bytes());
+    let err = AsepriteFile::load(&data).unwrap_err();
+    let LoadSpriteError::Parse(parse_error) = &err else {
+        panic!("unexpected error: {:?}", err);
+    };
+    assert_eq!(parse_error.offset, Some(144));
+    assert_eq!(
+        err.source().unwrap().to_string(),
+        "invalid chunk size: 3 at offset 144 in frame 0 in chunk 0"
+    );
+}
+
+#[test]
+fn test_load_lenient() {
+    let mut data = std::fs::read("./tests/layers.aseprite").unwrap();
+    let name = data.windows(7).position(|w| w == b"Layer 2").unwrap();
+    data[name] = 0xFF;
+    assert!(matches!(
+        AsepriteFile::load(&data),
+        Err(LoadSpriteError::Parse(_))
+    ));
+    let options = LoadOptions { strict: false };
+    let file = AsepriteFile::load_with_options(&data, &options).unwrap();
+    assert_eq!(file.diagnostics().len(), 1);
+    assert!(file.diagnostics()[0]
+        .to_string()
+        .starts_with("skipped invalid data: invalid UTF-8 string at offset"));
+    // The skipped layer is hidden and the cel of Layer 3 stays on Layer 3
+    assert_eq!(file.layers().len(), 3);
+    assert!(!file.layers()[1].visible);
+    assert_eq!(file.layers()[2].name, "Layer 3");
+    let cel = file.frames()[0]
+        .cels
+        .iter()
+        .find(|cel| cel.layer_index == 2)
+        .unwrap();
+    assert_eq!(file.layers()[cel.layer_index].name, "Layer 3");
+    let (width, height) = file.size();
+    let mut target = vec![0; usize::from(width) * usize::from(height) * 4];
+    file.render_frame(0, &mut target, &LayerSelection::Visible)
+        .unwrap();
+
+    // Valid files do not produce any diagnostics
+    let data = std::fs::read("./tests/layers.aseprite").unwrap();
+    let file = AsepriteFile::load_with_options(&data, &options).unwrap();
+    assert!(file.diagnostics().is_empty());
+    // The other layers are rendered like in the valid file
+    let mut expected = vec![0; target.len()];
+    file.render_frame(
+        0,
+        &mut expected,
+        &LayerSelection::Mask(vec![true, false, true]),
+    )
+    .unwrap();
+    assert_eq!(target, expected);
+}
+
+#[test]
+fn test_into_owned() {
+    fn assert_send<T: Send + 'static>(_: &T) {}
+    for name in ["groups", "indexed", "tilemap", "user_data"] {
+        let path = format!("./tests/{}.aseprite", name);
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let owned = AsepriteFile::load_from_path(&path).unwrap();
+        assert_send(&owned);
+        // Dropping the input buffer does not affect the owned file
+        let from_reader = {
+            let data = data.clone();
+            AsepriteFile::load_from_reader(data.as_slice()).unwrap()
+        };
+        let (width, height) = file.size();
+        let size = usize::from(width) * usize::from(height) * 4;
+        for frame_index in 0..file.frames().len() {
+            let mut expected = vec![0; size];
+            file.render_frame(frame_index, &mut expected, &LayerSelection::All)
+                .unwrap();
+            for owned in [&owned, &from_reader] {
+                let mut target = vec![0; size];
+                owned
+                    .render_frame(frame_index, &mut target, &LayerSelection::All)
+                    .unwrap();
+                assert_eq!(target, expected);
+            }
+        }
+        assert_eq!(owned.layers().len(), file.layers().len());
+        assert_eq!(owned.file.tags.len(), file.file.tags.len());
+        assert!(owned
+            .file
+            .tags
+            .iter()
+            .zip(&file.file.tags)
+            .all(|(a, b)| a.name == b.name));
+    }
+    assert!(matches!(
+        AsepriteFile::load_from_path("./tests/missing.aseprite"),
+        Err(LoadSpriteError::Io(_))
+    ));
+}
+
+#[test]
+fn test_pixel_format() {
+    let formats = [
+        PixelFormat::Bgra8,
+        PixelFormat::PremultipliedRgba8,
+        PixelFormat::Rgb8 {
+            background: [10, 20, 30],
+        },
+        PixelFormat::Gray8 { background: 40 },
+        PixelFormat::Rgba32FLinear,
+    ];
+    for name in ["combine", "groups", "indexed", "tilemap"] {
+        let path = format!("./tests/{}.aseprite", name);
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let mut renderer = file.renderer();
+        let (width, height) = file.size();
+        let pixel_count = usize::from(width) * usize::from(height);
+        for format in formats {
+            let options = RenderOptions {
+                format,
+                ..Default::default()
+            };
+            let size = pixel_count * format.bytes_per_pixel();
+            for frame_index in 0..file.frames().len() {
+                let mut rgba = vec![0; pixel_count * 4];
+                file.render_frame(frame_index, &mut rgba, &LayerSelection::Visible)
+                    .unwrap();
+                let mut expected = vec![0; size];
+                format.convert(&rgba, &mut expected);
+                let mut target = vec![0; size];
+                file.render_frame_with_options(
+                    frame_index,
+                    &mut target,
+                    &LayerSelection::Visible,
+                    &options,
+                )
+                .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, format);
+                renderer
+                    .render_frame_with_options(
+                        frame_index,
+                        &mut target,
+                        &LayerSelection::Visible,
+                        &options,
+                    )
+                    .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, format);
+                assert!(matches!(
+                    file.render_frame_with_options(
+                        frame_index,
+                        &mut target[..size - 1],
+                        &LayerSelection::Visible,
+                        &options,
+                    ),
+                    Err(LoadImageError::TargetBufferTooSmall)
+                ));
+            }
+            for (index, image) in file.images.iter().enumerate() {
+                let pixel_count = usize::from(image.width) * usize::from(image.height);
+                let mut rgba = vec![0; pixel_count * 4];
+                file.load_image(index, &mut rgba).unwrap();
+                let mut expected = vec![0; pixel_count * format.bytes_per_pixel()];
+                format.convert(&rgba, &mut expected);
+                let mut target = vec![0; expected.len()];
+                file.load_image_with_options(index, &mut target, &options)
+                    .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, format);
+            }
+            for (index, tilemap) in file.tilemaps.iter().enumerate() {
+                let tileset = &file.file.tilesets[tilemap.tileset_index];
+                let pixel_count = usize::from(tilemap.size.0 * tileset.width)
+                    * usize::from(tilemap.size.1 * tileset.height);
+                let mut rgba = vec![0; pixel_count * 4];
+                file.load_tilemap(index, &mut rgba).unwrap();
+                let mut expected = vec![0; pixel_count * format.bytes_per_pixel()];
+                format.convert(&rgba, &mut expected);
+                let mut target = vec![0; expected.len()];
+                file.load_tilemap_with_options(index, &mut target, &options)
+                    .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, format);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_render_frame_to_target() {
+    for name in ["combine", "groups", "tilemap", "z_index"] {
+        let path = format!("./tests/{}.aseprite", name);
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let mut renderer = file.renderer();
+        let (width, height) = file.size();
+        let (width, height) = (i32::from(width), i32::from(height));
+        let layers = LayerSelection::Visible;
+        for format in [PixelFormat::Rgba8, PixelFormat::Rgb8 { background: [0; 3] }] {
+            let options = RenderOptions {
+                format,
+                ..Default::default()
+            };
+            let bytes_per_pixel = format.bytes_per_pixel();
+            let mut frame = vec![0; (width * height) as usize * bytes_per_pixel];
+            file.render_frame_with_options(0, &mut frame, &layers, &options)
+                .unwrap();
+            // The buffer is 3 pixels wider than the frame and has 2 bytes
+            // of padding at the end of every row.
+            let buffer_width = width + 3;
+            let buffer_height = height + 1;
+            let stride = buffer_width as usize * bytes_per_pixel + 2;
+            for (x, y) in [
+                (0, 0),
+                (3, 1),
+                (-2, -1),
+                (width - 1, height),
+                (buffer_width, 0),
+                (-width, 0),
+            ] {
+                let mut buffer = vec![7; stride * buffer_height as usize];
+                let mut expected = buffer.clone();
+                for frame_y in 0..height {
+                    for frame_x in 0..width {
+                        let (buffer_x, buffer_y) = (frame_x + x, frame_y + y);
+                        if !(0..buffer_width).contains(&buffer_x)
+                            || !(0..buffer_height).contains(&buffer_y)
+                        {
+                            continue;
+                        }
+                        let src = (frame_y * width + frame_x) as usize * bytes_per_pixel;
+                        let dst = buffer_y as usize * stride + buffer_x as usize * bytes_per_pixel;
+                        expected[dst..dst + bytes_per_pixel]
+                            .copy_from_slice(&frame[src..src + bytes_per_pixel]);
+                    }
+                }
+                let mut cached = buffer.clone();
+                let target = RenderTarget {
+                    buffer: &mut buffer,
+                    stride,
+                    x,
+                    y,
+                };
+                file.render_frame_to_target(0, target, &layers, &options)
+                    .unwrap();
+                assert_eq!(buffer, expected, "{} {:?} {:?}", name, format, (x, y));
+                let target = RenderTarget {
+                    buffer: &mut cached,
+                    stride,
+                    x,
+                    y,
+                };
+                renderer
+                    .render_frame_to_target(0, target, &layers, &options)
+                    .unwrap();
+                assert_eq!(cached, expected, "{} {:?} {:?}", name, format, (x, y));
+            }
+        }
+        // The last row may be shorter than the stride
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        let target = RenderTarget {
+            buffer: &mut buffer,
+            stride: (width * 4) as usize + 4,
+            x: 0,
+            y: 0,
+        };
+        file.render_frame_to_target(0, target, &layers, &RenderOptions::default())
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_render_frame_region() {
+    for name in ["combine", "groups", "tilemap", "z_index"] {
+        let path = format!("./tests/{}.aseprite", name);
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let mut renderer = file.renderer();
+        let (width, height) = file.size();
+        let (width, height) = (u32::from(width), u32::from(height));
+        let layers = LayerSelection::All;
+        for frame_index in 0..file.frames().len() {
+            let mut frame = vec![0; (width * height * 4) as usize];
+            file.render_frame(frame_index, &mut frame, &layers).unwrap();
+            for rect in [
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+                Rect {
+                    x: width / 2,
+                    y: height / 3,
+                    width: width / 2,
+                    height: height / 2 + 1,
+                },
+                Rect {
+                    x: width / 2,
+                    y: 0,
+                    width: width + 2,
+                    height: 1,
+                },
+                Rect {
+                    x: u32::MAX,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                },
+            ] {
+                let mut expected = vec![0; (rect.width * rect.height * 4) as usize];
+                for y in rect.y..(rect.y + rect.height).min(height) {
+                    for x in rect.x..(rect.x.saturating_add(rect.width)).min(width) {
+                        let src = ((y * width + x) * 4) as usize;
+                        let dst = (((y - rect.y) * rect.width + x - rect.x) * 4) as usize;
+                        expected[dst..dst + 4].copy_from_slice(&frame[src..src + 4]);
+                    }
+                }
+                // Pixels outside of the sprite are cleared as well
+                let mut target = vec![0xaa; expected.len()];
+                file.render_frame_region(frame_index, rect, &mut target, &layers)
+                    .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, rect);
+                target.fill(0xaa);
+                renderer
+                    .render_frame_region(frame_index, rect, &mut target, &layers)
+                    .unwrap();
+                assert_eq!(target, expected, "{} {:?}", name, rect);
+            }
+        }
+    }
+
+    let data = std::fs::read("./tests/combine.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let rect = Rect {
+        x: 0,
+        y: 0,
+        width: 4,
+        height: 4,
+    };
+    let mut target = [0; 4 * 4 * 4];
+    assert!(matches!(
+        file.render_frame_region(0, rect, &mut target[1..], &LayerSelection::All),
+        Err(LoadImageError::TargetBufferTooSmall)
+    ));
+}
+
+#[test]
+fn test_palettes() {
+    let data = std::fs::read("./tests/palettes.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let first_frames: Vec<_> = file.file.palettes.iter().map(|(i, _)| *i).collect();
+    assert_eq!(first_frames, [0, 1, 2]);
+
+    const R: [u8; 4] = [255, 0, 0, 255];
+    const G: [u8; 4] = [0, 255, 0, 255];
+    const B: [u8; 4] = [0, 0, 255, 255];
+    // The palette of frame 1 swaps red and green and frame 2 replaces
+    // green with blue. Frame 3 keeps the palette of frame 2.
+    let expected = [[R, G], [G, R], [G, B], [G, B]];
+    let mut renderer = file.renderer();
+    for (frame_index, expected) in expected.iter().enumerate() {
+        let palette = file.palette(frame_index).unwrap();
+        assert_eq!(palette.colors[0].alpha, 0);
+        let mut target = [0; 8];
+        file.render_frame(frame_index, &mut target, &LayerSelection::Visible)
+            .unwrap();
+        assert_eq!(target, expected.concat()[..], "frame {}", frame_index);
+        // The linked cel is decoded again if the palette changes
+        let mut target = [0; 8];
+        renderer
+            .render_frame(frame_index, &mut target, &LayerSelection::Visible)
+            .unwrap();
+        assert_eq!(target, expected.concat()[..], "frame {}", frame_index);
+    }
+    // Images use the palette of the frame they are defined in
+    let mut target = [0; 8];
+    file.load_image(0, &mut target).unwrap();
+    assert_eq!(target, [R, G].concat()[..]);
+    // The palette of the file is the palette of the last frame
+    assert_eq!(
+        file.file.palette.as_ref().unwrap().colors[..3],
+        file.palette(3).unwrap().colors[..3]
+    );
+}
+
+#[test]
+fn test_indexed_background() {
+    let data = std::fs::read("./tests/indexed_background.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    assert!(file.layers()[0].background);
+    assert!(!file.layers()[1].background);
+    // The transparent index is opaque in the background layer only
+    let mut target = [0; 8];
+    file.render_frame(0, &mut target, &LayerSelection::Visible)
+        .unwrap();
+    assert_eq!(target, [10, 20, 30, 255, 0, 255, 0, 255]);
+    file.load_image(0, &mut target).unwrap();
+    assert_eq!(target, [10, 20, 30, 255, 255, 0, 0, 255]);
+    file.load_image(1, &mut target).unwrap();
+    assert_eq!(target, [0, 0, 0, 0, 0, 255, 0, 255]);
+    // The palette keeps the color of the transparent index
+    assert_eq!(file.palette(0).unwrap().colors[0].alpha, 255);
+}
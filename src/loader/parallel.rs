@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use super::{AsepriteFile, LayerSelection, LoadImageError};
+
+/// Parallel rendering of multiple frames
+///
+/// Every worker thread uses its own [`Renderer`](super::Renderer) so the
+/// images are decoded in parallel, too. The rendered frames are identical
+/// to the ones rendered by [`AsepriteFile::render_frame`].
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+impl AsepriteFile<'_> {
+    /// Render all frames in parallel into newly allocated RGBA8 buffers.
+    pub fn render_all_frames(
+        &self,
+        layers: &LayerSelection,
+    ) -> Result<Vec<Vec<u8>>, LoadImageError> {
+        self.render_frames(0..self.frames.len(), layers)
+    }
+
+    /// Render the frames in `range` in parallel into newly allocated
+    /// RGBA8 buffers.
+    pub fn render_frames(
+        &self,
+        range: Range<usize>,
+        layers: &LayerSelection,
+    ) -> Result<Vec<Vec<u8>>, LoadImageError> {
+        let frame_size = self.frame_buffer_size();
+        range
+            .into_par_iter()
+            .map_init(
+                || self.renderer(),
+                |renderer, frame_index| {
+                    let mut target = vec![0; frame_size];
+                    renderer.render_frame(frame_index, &mut target, layers)?;
+                    Ok(target)
+                },
+            )
+            .collect()
+    }
+
+    /// Render the frames in `range` in parallel into `target`.
+    ///
+    /// The frames are stored one after another and every frame takes up
+    /// `width * height * 4` bytes. Like [`render_frame`](Self::render_frame)
+    /// this does not clear the `target` buffer: every frame is blended into
+    /// the current content of its part of the buffer.
+    pub fn render_frames_into(
+        &self,
+        range: Range<usize>,
+        target: &mut [u8],
+        layers: &LayerSelection,
+    ) -> Result<(), LoadImageError> {
+        let frame_size = self.frame_buffer_size();
+        let target_size = range
+            .len()
+            .checked_mul(frame_size)
+            .ok_or(LoadImageError::TargetBufferTooSmall)?;
+        if target.len() < target_size {
+            return Err(LoadImageError::TargetBufferTooSmall);
+        }
+        if frame_size == 0 {
+            // Chunks of an empty buffer can't be created. Rendering
+            // still checks the frame indices.
+            return range
+                .into_iter()
+                .try_for_each(|frame_index| self.render_frame(frame_index, &mut [], layers));
+        }
+        target[..target_size]
+            .par_chunks_mut(frame_size)
+            .zip(range)
+            .try_for_each_init(
+                || self.renderer(),
+                |renderer, (target, frame_index)| {
+                    renderer.render_frame(frame_index, target, layers)
+                },
+            )
+    }
+
+    /// Size of a RGBA8 buffer containing a single frame
+    fn frame_buffer_size(&self) -> usize {
+        let (width, height) = self.size();
+        usize::from(width) * usize::from(height) * 4
+    }
+}
+
+#[test]
+fn test_render_frames() {
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("aseprite") {
+            continue;
+        }
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let frame_count = file.frames().len();
+        let frame_size = file.frame_buffer_size();
+        let expected: Vec<Vec<u8>> = (0..frame_count)
+            .map(|frame_index| {
+                let mut target = vec![0; frame_size];
+                file.render_frame(frame_index, &mut target, &LayerSelection::Visible)
+                    .unwrap();
+                target
+            })
+            .collect();
+        let frames = file.render_all_frames(&LayerSelection::Visible).unwrap();
+        assert_eq!(frames, expected);
+        let frames = file
+            .render_frames(1.min(frame_count)..frame_count, &LayerSelection::Visible)
+            .unwrap();
+        assert_eq!(frames, expected[1.min(frame_count)..]);
+        let mut target = vec![0; frame_count * frame_size];
+        file.render_frames_into(0..frame_count, &mut target, &LayerSelection::Visible)
+            .unwrap();
+        assert_eq!(target, expected.concat());
+
+        assert!(matches!(
+            file.render_frames(0..frame_count + 1, &LayerSelection::Visible),
+            Err(LoadImageError::FrameIndexOutOfRange(_))
+        ));
+        assert!(matches!(
+            file.render_frames_into(0..frame_count + 1, &mut target, &LayerSelection::Visible),
+            Err(LoadImageError::TargetBufferTooSmall)
+        ));
+    }
+}
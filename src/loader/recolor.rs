@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use crate::binary::palette::Palette;
+
+/// Colors which are replaced while the pixels of a sprite are decoded
+///
+/// This allows rendering palette variants such as team colors or skins
+/// without storing a copy of every frame for every variant.
+// Recolors are only stored in `RenderOptions` so the size difference of
+// the variants does not matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recolor {
+    /// Replace the palette of an indexed sprite. The colors are looked up
+    /// in this palette instead of the palette of the rendered frame. The
+    /// transparent index of the file stays transparent. This has no
+    /// effect on sprites which are not indexed.
+    Palette(Palette),
+    /// Replace every RGBA8 color `[r, g, b, a]` which is a key of the
+    /// table by its value. Colors which are not in the table are kept.
+    /// For indexed sprites the colors of the palette are replaced.
+    Colors(HashMap<[u8; 4], [u8; 4]>),
+}
+
+impl Recolor {
+    /// Get the replacement palette for indexed sprites
+    pub(crate) fn palette(&self) -> Option<&Palette> {
+        match self {
+            Self::Palette(palette) => Some(palette),
+            Self::Colors(_) => None,
+        }
+    }
+
+    /// Get the color which replaces the given RGBA8 color
+    pub(crate) fn map(&self, color: [u8; 4]) -> [u8; 4] {
+        match self {
+            Self::Palette(_) => color,
+            Self::Colors(colors) => colors.get(&color).copied().unwrap_or(color),
+        }
+    }
+}
+
+#[test]
+fn test_recolor_palette() {
+    use super::{AsepriteFile, LayerSelection, RenderOptions};
+
+    let data = std::fs::read("./tests/indexed_background.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let mut palette = file.palette(0).unwrap().clone();
+    palette.colors[0].red = 100;
+    palette.colors[2].red = 200;
+    let options = RenderOptions {
+        recolor: Some(Recolor::Palette(palette)),
+        ..Default::default()
+    };
+    let mut target = [0; 8];
+    file.render_frame_with_options(0, &mut target, &LayerSelection::Visible, &options)
+        .unwrap();
+    assert_eq!(target, [100, 20, 30, 255, 200, 255, 0, 255]);
+    // The transparent index stays transparent
+    file.load_image_with_options(1, &mut target, &options)
+        .unwrap();
+    assert_eq!(target, [0, 0, 0, 0, 200, 255, 0, 255]);
+
+    // The renderer decodes the images again if the recolor changes
+    let mut renderer = file.renderer();
+    let mut expected = [0; 8];
+    for options in [RenderOptions::default(), options] {
+        file.render_frame_with_options(0, &mut expected, &LayerSelection::Visible, &options)
+            .unwrap();
+        renderer
+            .render_frame_with_options(0, &mut target, &LayerSelection::Visible, &options)
+            .unwrap();
+        assert_eq!(target, expected);
+    }
+}
+
+#[test]
+fn test_recolor_colors() {
+    use super::{AsepriteFile, PixelFormat, RenderOptions};
+
+    let data = std::fs::read("./tests/combine.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let size = usize::from(file.images[0].width) * usize::from(file.images[0].height) * 4;
+    let mut original = vec![0; size];
+    file.load_image(0, &mut original).unwrap();
+    let from: [u8; 4] = original
+        .chunks_exact(4)
+        .find(|pixel| pixel[3] != 0)
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let to = [1, 2, 3, 4];
+    let options = RenderOptions {
+        format: PixelFormat::Bgra8,
+        recolor: Some(Recolor::Colors(HashMap::from([(from, to)]))),
+    };
+    let mut target = vec![0; size];
+    file.load_image_with_options(0, &mut target, &options)
+        .unwrap();
+    for (pixel, original) in target.chunks_exact(4).zip(original.chunks_exact(4)) {
+        let expected = if original == from {
+            to
+        } else {
+            original.try_into().unwrap()
+        };
+        assert_eq!(pixel, [expected[2], expected[1], expected[0], expected[3]]);
+    }
+}
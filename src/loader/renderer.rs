@@ -0,0 +1,211 @@
+use super::{
+    region_target, target_bounds, AsepriteFile, CachedImage, CelPixels, LayerSelection,
+    LoadImageError, PixelFormat, Recolor, Rect, RenderOptions, RenderTarget, Scratch,
+};
+
+/// Renders the frames of an [`AsepriteFile`] and caches the decoded images.
+///
+/// Every image and tilemap is decoded at most once, even if it is used by
+/// multiple frames via linked cels. The buffers used for compositing are
+/// reused as well, so rendering a frame whose images have already been
+/// decoded does not allocate.
+#[derive(Debug)]
+pub struct Renderer<'a> {
+    file: &'a AsepriteFile<'a>,
+    /// Decoded RGBA8 pixels indexed like [`AsepriteFile::images`]
+    images: Vec<Option<CachedImage>>,
+    /// Decoded RGBA8 pixels indexed like [`AsepriteFile::tilemaps`]
+    tilemaps: Vec<Option<CachedImage>>,
+    /// Colors which were replaced while decoding the cached images
+    recolor: Option<Recolor>,
+    scratch: Scratch,
+}
+
+impl<'a> Renderer<'a> {
+    /// Create a renderer for the given file
+    pub fn new(file: &'a AsepriteFile<'a>) -> Self {
+        Self {
+            file,
+            images: vec![None; file.images.len()],
+            tilemaps: vec![None; file.tilemaps.len()],
+            recolor: None,
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// Get the file which is rendered
+    pub fn file(&self) -> &'a AsepriteFile<'a> {
+        self.file
+    }
+
+    /// Render a frame for a given frame index. This works like
+    /// [`AsepriteFile::render_frame`] but decodes every image only once.
+    /// The layers are blended into the current content of `target`.
+    pub fn render_frame(
+        &mut self,
+        frame_index: usize,
+        target: &mut [u8],
+        layers: &LayerSelection,
+    ) -> Result<(), LoadImageError> {
+        let target = self.file.frame_target(target, PixelFormat::Rgba8)?;
+        self.composite(
+            frame_index,
+            target,
+            layers,
+            &RenderOptions::default(),
+            false,
+        )
+    }
+
+    /// Render a frame for a given frame index using the given options.
+    /// This works like [`AsepriteFile::render_frame_with_options`].
+    pub fn render_frame_with_options(
+        &mut self,
+        frame_index: usize,
+        target: &mut [u8],
+        layers: &LayerSelection,
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        let target = self.file.frame_target(target, options.format)?;
+        self.render_frame_to_target(frame_index, target, layers, options)
+    }
+
+    /// Render the part of a frame which is inside of `rect`. This works
+    /// like [`AsepriteFile::render_frame_region`].
+    pub fn render_frame_region(
+        &mut self,
+        frame_index: usize,
+        rect: Rect,
+        target: &mut [u8],
+        layers: &LayerSelection,
+    ) -> Result<(), LoadImageError> {
+        // The region has already been cleared by `region_target`
+        let target = region_target(rect, target)?;
+        self.composite(
+            frame_index,
+            target,
+            layers,
+            &RenderOptions::default(),
+            false,
+        )
+    }
+
+    /// Render a frame for a given frame index into a region of a larger
+    /// buffer. This works like [`AsepriteFile::render_frame_to_target`].
+    pub fn render_frame_to_target(
+        &mut self,
+        frame_index: usize,
+        target: RenderTarget<'_>,
+        layers: &LayerSelection,
+        options: &RenderOptions,
+    ) -> Result<(), LoadImageError> {
+        self.composite(frame_index, target, layers, options, true)
+    }
+
+    /// Decode the images of the frame which are not cached yet and
+    /// composite the frame. See [`AsepriteFile::composite_frame`].
+    fn composite(
+        &mut self,
+        frame_index: usize,
+        target: RenderTarget<'_>,
+        layers: &LayerSelection,
+        options: &RenderOptions,
+        overwrite: bool,
+    ) -> Result<(), LoadImageError> {
+        let file = self.file;
+        let bounds = target_bounds(&target, file.size(), options.format.bytes_per_pixel());
+        if self.recolor != options.recolor {
+            self.images.fill(None);
+            self.tilemaps.fill(None);
+            self.recolor.clone_from(&options.recolor);
+        }
+        file.decode_frame_cels(
+            frame_index,
+            bounds,
+            layers,
+            options.recolor.as_ref(),
+            &mut self.images,
+            &mut self.tilemaps,
+        )?;
+        let pixels = CelPixels::Cached {
+            images: &self.images,
+            tilemaps: &self.tilemaps,
+        };
+        file.composite_frame(
+            frame_index,
+            target,
+            layers,
+            options,
+            pixels,
+            &mut self.scratch,
+            overwrite,
+        )
+    }
+
+    /// Drop all decoded images and buffers to free their memory.
+    pub fn clear_cache(&mut self) {
+        self.images.fill(None);
+        self.tilemaps.fill(None);
+        self.scratch = Scratch::default();
+    }
+}
+
+#[test]
+fn test_renderer() {
+    for entry in std::fs::read_dir("./tests").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("aseprite") {
+            continue;
+        }
+        let data = std::fs::read(&path).unwrap();
+        let file = AsepriteFile::load(&data).unwrap();
+        let mut renderer = file.renderer();
+        let (width, height) = file.size();
+        let size = usize::from(width) * usize::from(height) * 4;
+        for layers in [LayerSelection::Visible, LayerSelection::All] {
+            // Render all frames twice to use the cached images
+            for frame_index in (0..file.frames().len()).chain(0..file.frames().len()) {
+                let mut expected = vec![0; size];
+                file.render_frame(frame_index, &mut expected, &layers)
+                    .unwrap();
+                let mut target = vec![0; size];
+                renderer
+                    .render_frame(frame_index, &mut target, &layers)
+                    .unwrap();
+                assert_eq!(target, expected, "{:?} frame {}", path, frame_index);
+            }
+        }
+        // Every cel of all layers has been decoded
+        assert!(renderer.images.iter().all(Option::is_some));
+        renderer.clear_cache();
+        assert!(renderer.images.iter().all(Option::is_none));
+        assert!(matches!(
+            renderer.render_frame(
+                file.frames().len(),
+                &mut vec![0; size],
+                &LayerSelection::All
+            ),
+            Err(LoadImageError::FrameIndexOutOfRange(_))
+        ));
+    }
+}
+
+#[test]
+fn test_render_frame_region() {
+    let data = std::fs::read("./tests/combine.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let mut renderer = file.renderer();
+    // Cels outside of the region are not decoded
+    let rect = Rect {
+        x: u32::from(file.size().0),
+        y: 0,
+        width: 4,
+        height: 4,
+    };
+    let mut target = vec![0; 4 * 4 * 4];
+    renderer
+        .render_frame_region(0, rect, &mut target, &LayerSelection::All)
+        .unwrap();
+    assert!(renderer.images.iter().all(Option::is_none));
+    assert!(target.iter().all(|&b| b == 0));
+}
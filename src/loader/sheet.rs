@@ -0,0 +1,536 @@
+use super::{AsepriteFile, LayerSelection, LoadImageError};
+
+/// A rectangle in pixel coordinates
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    /// X position of the left edge
+    pub x: u32,
+    /// Y position of the top edge
+    pub y: u32,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+}
+
+/// The way frames are arranged in a [`SpriteSheet`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SheetLayout {
+    /// Frames are placed row by row on a grid which is as close to a
+    /// square as possible.
+    #[default]
+    Grid,
+    /// Frames are placed row by row with the given number of columns.
+    Rows {
+        /// Number of frames per row
+        columns: usize,
+    },
+    /// Frames are placed column by column with the given number of rows.
+    Columns {
+        /// Number of frames per column
+        rows: usize,
+    },
+    /// Frames are packed as tightly as possible. This works best in
+    /// combination with [`SheetOptions::trim`].
+    Packed,
+}
+
+/// Options for [`AsepriteFile::sprite_sheet`]
+///
+/// The padding options use the same semantics as the sprite sheet export
+/// of Aseprite.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SheetOptions {
+    /// Arrangement of the frames
+    pub layout: SheetLayout,
+    /// Layers to render
+    pub layers: LayerSelection,
+    /// Space between the edges of the sheet and the frames
+    pub border_padding: u32,
+    /// Space between two frames
+    pub shape_padding: u32,
+    /// Transparent space added around every frame. This space is part
+    /// of the frame rectangle.
+    pub inner_padding: u32,
+    /// Repeat the edge pixels of every frame by one pixel. This avoids
+    /// bleeding of neighbouring frames when sampling the texture with
+    /// filtering enabled. The extruded pixels are not part of the frame
+    /// rectangle.
+    pub extrude: bool,
+    /// Remove transparent borders from every frame
+    pub trim: bool,
+}
+
+/// A RGBA8 texture containing multiple frames
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    /// Width of the sheet in pixels
+    pub width: u32,
+    /// Height of the sheet in pixels
+    pub height: u32,
+    /// RGBA8 pixels of the sheet
+    pub pixels: Vec<u8>,
+    /// Position and metadata for each frame in the order of
+    /// [`AsepriteFile::frames`]
+    pub frames: Vec<SheetFrame>,
+}
+
+/// A frame of a [`SpriteSheet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetFrame {
+    /// Index into [`AsepriteFile::frames`]
+    pub frame_index: usize,
+    /// Position of the frame in the sheet
+    pub rect: Rect,
+    /// Part of the original frame contained in `rect` relative to the
+    /// top left corner of the frame. Unless the frame was trimmed this
+    /// covers the whole frame.
+    pub source_rect: Rect,
+    /// Size of the original frame `(width, height)`
+    pub source_size: (u32, u32),
+    /// Whether transparent borders were removed from the frame
+    pub trimmed: bool,
+    /// Frame duration in milliseconds
+    pub duration: u16,
+    /// Indices into [`AsepriteFile::tags`] of all tags containing this frame
+    pub tags: Vec<usize>,
+}
+
+impl AsepriteFile<'_> {
+    /// Render all frames into a single texture.
+    pub fn sprite_sheet(&self, options: &SheetOptions) -> Result<SpriteSheet, LoadImageError> {
+        let (width, height) = self.size();
+        let (width, height) = (u32::from(width), u32::from(height));
+        let mut frame_pixels = Vec::with_capacity(self.frames.len());
+        let mut frames = Vec::with_capacity(self.frames.len());
+        let mut renderer = self.renderer();
+        for (frame_index, frame) in self.frames.iter().enumerate() {
+            let mut pixels = vec![0; width as usize * height as usize * 4];
+            renderer.render_frame(frame_index, &mut pixels, &options.layers)?;
+            let source_rect = if options.trim {
+                trim(&pixels, width, height)
+            } else {
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                }
+            };
+            frames.push(SheetFrame {
+                frame_index,
+                rect: Rect {
+                    x: 0,
+                    y: 0,
+                    width: add_padding(source_rect.width, options.inner_padding)?,
+                    height: add_padding(source_rect.height, options.inner_padding)?,
+                },
+                source_rect,
+                source_size: (width, height),
+                trimmed: source_rect.width != width || source_rect.height != height,
+                duration: frame.duration,
+                tags: self
+                    .tags
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tag)| tag.range.contains(&(frame_index as u16)))
+                    .map(|(tag_index, _)| tag_index)
+                    .collect(),
+            });
+            frame_pixels.push(pixels);
+        }
+
+        let extrude = u32::from(options.extrude);
+        let slots = frames
+            .iter()
+            .map(|f| {
+                Some((
+                    f.rect.width.checked_add(2 * extrude)?,
+                    f.rect.height.checked_add(2 * extrude)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(LoadImageError::SheetTooLarge)?;
+        let (positions, sheet_width, sheet_height) =
+            layout(&slots, options.layout, options.shape_padding)
+                .ok_or(LoadImageError::SheetTooLarge)?;
+        let sheet_width = add_padding(sheet_width, options.border_padding)?;
+        let sheet_height = add_padding(sheet_height, options.border_padding)?;
+        let buffer_size = usize::try_from(u64::from(sheet_width) * u64::from(sheet_height))
+            .ok()
+            .and_then(|count| count.checked_mul(4))
+            .filter(|&size| isize::try_from(size).is_ok())
+            .ok_or(LoadImageError::SheetTooLarge)?;
+
+        let mut sheet = SpriteSheet {
+            width: sheet_width,
+            height: sheet_height,
+            pixels: vec![0; buffer_size],
+            frames,
+        };
+        for ((frame, pixels), (x, y)) in sheet.frames.iter_mut().zip(&frame_pixels).zip(positions) {
+            frame.rect.x = options.border_padding + x + extrude;
+            frame.rect.y = options.border_padding + y + extrude;
+            let src = frame.source_rect;
+            for row in 0..src.height {
+                let src_start = ((src.y + row) as usize * width as usize + src.x as usize) * 4;
+                let dst_x = frame.rect.x + options.inner_padding;
+                let dst_y = frame.rect.y + options.inner_padding + row;
+                let dst_start = (dst_y as usize * sheet_width as usize + dst_x as usize) * 4;
+                let len = src.width as usize * 4;
+                sheet.pixels[dst_start..dst_start + len]
+                    .copy_from_slice(&pixels[src_start..src_start + len]);
+            }
+            if options.extrude {
+                extrude_rect(&mut sheet.pixels, sheet_width, frame.rect);
+            }
+        }
+        Ok(sheet)
+    }
+}
+
+/// Add `padding` on both sides of `size`
+fn add_padding(size: u32, padding: u32) -> Result<u32, LoadImageError> {
+    padding
+        .checked_mul(2)
+        .and_then(|padding| size.checked_add(padding))
+        .ok_or(LoadImageError::SheetTooLarge)
+}
+
+/// Find the smallest rectangle containing all non-transparent pixels.
+/// Fully transparent images result in an empty rectangle.
+fn trim(pixels: &[u8], width: u32, height: u32) -> Rect {
+    let opaque = |x: u32, y: u32| pixels[(y as usize * width as usize + x as usize) * 4 + 3] != 0;
+    let Some(top) = (0..height).find(|&y| (0..width).any(|x| opaque(x, y))) else {
+        return Rect::default();
+    };
+    let bottom = (top..height)
+        .rev()
+        .find(|&y| (0..width).any(|x| opaque(x, y)))
+        .unwrap_or(top);
+    let left = (0..width)
+        .find(|&x| (top..=bottom).any(|y| opaque(x, y)))
+        .unwrap_or(0);
+    let right = (left..width)
+        .rev()
+        .find(|&x| (top..=bottom).any(|y| opaque(x, y)))
+        .unwrap_or(left);
+    Rect {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    }
+}
+
+/// Copy the edge pixels of `rect` one pixel outwards.
+fn extrude_rect(pixels: &mut [u8], sheet_width: u32, rect: Rect) {
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let index = |x: u32, y: u32| (y as usize * sheet_width as usize + x as usize) * 4;
+    let copy = |pixels: &mut [u8], from: usize, to: usize| {
+        pixels.copy_within(from..from + 4, to);
+    };
+    let (left, top) = (rect.x, rect.y);
+    let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+    for x in left..=right {
+        copy(pixels, index(x, top), index(x, top - 1));
+        copy(pixels, index(x, bottom), index(x, bottom + 1));
+    }
+    // The corners are filled by extruding the rows which were
+    // extruded above.
+    for y in top - 1..=bottom + 1 {
+        copy(pixels, index(left, y), index(left - 1, y));
+        copy(pixels, index(right, y), index(right + 1, y));
+    }
+}
+
+/// Positions of all slots and the size of the sheet `(positions, width, height)`
+type Layout = (Vec<(u32, u32)>, u32, u32);
+
+/// Calculate the positions of all `slots` and the total size of the sheet
+/// without the border padding. Returns `None` if the size of the sheet
+/// does not fit into an `u32`.
+fn layout(slots: &[(u32, u32)], layout: SheetLayout, padding: u32) -> Option<Layout> {
+    if slots.is_empty() {
+        return Some((Vec::new(), 0, 0));
+    }
+    let cell_width = slots.iter().map(|s| s.0).max().unwrap_or(0);
+    let cell_height = slots.iter().map(|s| s.1).max().unwrap_or(0);
+    let count = slots.len();
+    let grid = |columns: usize, rows: usize, by_rows: bool| {
+        // The positions are smaller than the size of the sheet so only
+        // the size needs to be checked.
+        let size = |cells: usize, cell_size: u32| {
+            u32::try_from(cells)
+                .ok()?
+                .checked_mul(cell_size.checked_add(padding)?)
+                .map(|size| size - padding)
+        };
+        let width = size(columns, cell_width)?;
+        let height = size(rows, cell_height)?;
+        let positions = (0..count)
+            .map(|i| {
+                let (column, row) = if by_rows {
+                    (i % columns, i / columns)
+                } else {
+                    (i / rows, i % rows)
+                };
+                (
+                    column as u32 * (cell_width + padding),
+                    row as u32 * (cell_height + padding),
+                )
+            })
+            .collect();
+        Some((positions, width, height))
+    };
+    match layout {
+        SheetLayout::Grid => {
+            let columns = (1..=count).find(|c| c * c >= count).unwrap_or(count);
+            grid(columns, (count + columns - 1) / columns, true)
+        }
+        SheetLayout::Rows { columns } => {
+            let columns = columns.clamp(1, count);
+            grid(columns, (count + columns - 1) / columns, true)
+        }
+        SheetLayout::Columns { rows } => {
+            let rows = rows.clamp(1, count);
+            grid((count + rows - 1) / rows, rows, false)
+        }
+        SheetLayout::Packed => pack(slots, padding),
+    }
+}
+
+/// Pack the `slots` using a bottom-left skyline algorithm. Multiple sheet
+/// widths are tried and the one resulting in the smallest and most square
+/// sheet is used.
+fn pack(slots: &[(u32, u32)], padding: u32) -> Option<Layout> {
+    // All slots placed next to each other or on top of each other plus
+    // the padding added by `pack_skyline` must fit into an `u32` so the
+    // skyline can't overflow.
+    let total = |size: fn(&(u32, u32)) -> u32| {
+        slots.iter().try_fold(padding, |total, slot| {
+            total.checked_add(size(slot))?.checked_add(padding)
+        })
+    };
+    let _ = total(|slot| slot.1)?;
+    let max_width = total(|slot| slot.0)? - padding;
+    let mut order: Vec<usize> = (0..slots.len()).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(slots[i].1), std::cmp::Reverse(slots[i].0)));
+    let min_width = slots.iter().map(|s| s.0).max().unwrap_or(0);
+    const STEPS: u32 = 64;
+    let step = ((max_width - min_width) / STEPS).max(1);
+    let score = |w: u32, h: u32| (u64::from(w) * u64::from(h), w.abs_diff(h));
+    let mut best: Option<Layout> = None;
+    for max_sheet_width in (min_width..=max_width).step_by(step as usize) {
+        let (positions, width, height) = pack_skyline(slots, &order, padding, max_sheet_width);
+        if best
+            .as_ref()
+            .map_or(true, |b| score(width, height) < score(b.1, b.2))
+        {
+            best = Some((positions, width, height));
+        }
+    }
+    Some(best.unwrap_or_default())
+}
+
+/// Pack the `slots` in the given `order` into a sheet which is at most
+/// `max_width` wide.
+fn pack_skyline(slots: &[(u32, u32)], order: &[usize], padding: u32, max_width: u32) -> Layout {
+    // The skyline consists of segments `(x, y, width)` which cover the
+    // whole width of the sheet from left to right. The padding is added
+    // to the size of every slot so it is only needed between slots.
+    let max_width = max_width + padding;
+    let mut skyline = vec![(0u32, 0u32, max_width)];
+    let mut positions = vec![(0, 0); slots.len()];
+    let (mut width, mut height) = (0, 0);
+    for &i in order {
+        let (w, h) = slots[i];
+        let padded_w = w + padding;
+        // Find the lowest position (and leftmost on ties) at the start of
+        // a segment where the slot fits.
+        let mut found: Option<(u32, u32)> = None;
+        for (start, &(x, _, _)) in skyline.iter().enumerate() {
+            if x + padded_w > max_width {
+                break;
+            }
+            let y = skyline[start..]
+                .iter()
+                .take_while(|segment| segment.0 < x + padded_w)
+                .map(|segment| segment.1)
+                .max()
+                .unwrap_or(0);
+            if found.map_or(true, |f| y < f.1) {
+                found = Some((x, y));
+            }
+        }
+        // Slots which are wider than the sheet are placed at the left
+        // edge below everything else.
+        let (x, y) = found.unwrap_or_else(|| (0, skyline.iter().map(|s| s.1).max().unwrap_or(0)));
+        positions[i] = (x, y);
+        width = width.max(x + w);
+        height = height.max(y + h);
+
+        // Replace the covered part of the skyline by the new segment.
+        let top = y + h + padding;
+        let end = x + padded_w;
+        let mut new_skyline = Vec::with_capacity(skyline.len() + 2);
+        for &(sx, sy, sw) in &skyline {
+            if sx + sw <= x || sx >= end {
+                new_skyline.push((sx, sy, sw));
+                continue;
+            }
+            if sx < x {
+                new_skyline.push((sx, sy, x - sx));
+            }
+            if sx + sw > end {
+                new_skyline.push((end, sy, sx + sw - end));
+            }
+        }
+        new_skyline.push((x, top, padded_w));
+        new_skyline.sort_by_key(|s| s.0);
+        skyline = new_skyline;
+    }
+    (positions, width, height)
+}
+
+#[test]
+fn test_trim() {
+    let mut pixels = vec![0; 4 * 3 * 4];
+    assert_eq!(trim(&pixels, 4, 3), Rect::default());
+    pixels[(4 + 1) * 4 + 3] = 255;
+    pixels[(2 * 4 + 2) * 4 + 3] = 1;
+    assert_eq!(
+        trim(&pixels, 4, 3),
+        Rect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2
+        }
+    );
+}
+
+#[test]
+fn test_sprite_sheet() {
+    let data = std::fs::read("./tests/z_index.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let sheet = file
+        .sprite_sheet(&SheetOptions {
+            layout: SheetLayout::Rows { columns: 2 },
+            border_padding: 1,
+            shape_padding: 2,
+            inner_padding: 1,
+            extrude: true,
+            ..Default::default()
+        })
+        .unwrap();
+    // Every slot is 1px + 2 * 1px inner padding + 2 * 1px extrusion = 5px
+    assert_eq!(
+        (sheet.width, sheet.height),
+        (1 + 5 + 2 + 5 + 1, 1 + 5 + 2 + 5 + 1)
+    );
+    let rects: Vec<_> = sheet.frames.iter().map(|f| (f.rect.x, f.rect.y)).collect();
+    assert_eq!(rects, [(2, 2), (9, 2), (2, 9)]);
+    assert_eq!(sheet.frames[0].rect.width, 3);
+    let pixel = |x: u32, y: u32| {
+        let i = (y * sheet.width + x) as usize * 4;
+        &sheet.pixels[i..i + 4]
+    };
+    // Inner padding is transparent and extruded
+    assert_eq!(pixel(1, 1), [0, 0, 0, 0]);
+    assert_eq!(pixel(3, 3), [0, 255, 0, 255]);
+    assert_eq!(pixel(10, 3), [255, 0, 0, 255]);
+    assert_eq!(pixel(3, 10), [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_sprite_sheet_packed() {
+    let data = std::fs::read("./tests/groups.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let sheet = file
+        .sprite_sheet(&SheetOptions {
+            layout: SheetLayout::Packed,
+            layers: file.select_layers_by_name(&["sleeve"]),
+            trim: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!((sheet.width, sheet.height), (1, 1));
+    let frame = &sheet.frames[0];
+    assert!(frame.trimmed);
+    assert_eq!(frame.source_size, (2, 1));
+    assert_eq!(
+        frame.source_rect,
+        Rect {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1
+        }
+    );
+    assert_eq!(sheet.pixels, [255, 0, 0, 128]);
+}
+
+#[test]
+fn test_pack() {
+    let slots = [(4, 4), (2, 2), (2, 2), (2, 2), (2, 2)];
+    let (positions, width, height) = pack(&slots, 0).unwrap();
+    assert_eq!(width * height, 32);
+    assert_eq!(positions[0], (0, 0));
+    for (i, a) in positions.iter().enumerate() {
+        for (j, b) in positions.iter().enumerate().skip(i + 1) {
+            let overlap_x = a.0 < b.0 + slots[j].0 && b.0 < a.0 + slots[i].0;
+            let overlap_y = a.1 < b.1 + slots[j].1 && b.1 < a.1 + slots[i].1;
+            assert!(!(overlap_x && overlap_y), "slots {} and {} overlap", i, j);
+        }
+    }
+}
+
+#[test]
+fn test_sprite_sheet_too_large() {
+    let data = std::fs::read("./tests/z_index.aseprite").unwrap();
+    let file = AsepriteFile::load(&data).unwrap();
+    let large = u32::MAX / 2;
+    for layout in [
+        SheetLayout::Grid,
+        SheetLayout::Rows { columns: 3 },
+        SheetLayout::Columns { rows: 3 },
+        SheetLayout::Packed,
+    ] {
+        for options in [
+            SheetOptions {
+                inner_padding: u32::MAX,
+                ..Default::default()
+            },
+            SheetOptions {
+                inner_padding: large,
+                extrude: true,
+                ..Default::default()
+            },
+            SheetOptions {
+                shape_padding: large,
+                ..Default::default()
+            },
+            SheetOptions {
+                border_padding: large,
+                ..Default::default()
+            },
+            SheetOptions {
+                border_padding: large / 2,
+                ..Default::default()
+            },
+        ] {
+            let options = SheetOptions { layout, ..options };
+            assert!(
+                matches!(
+                    file.sprite_sheet(&options),
+                    Err(LoadImageError::SheetTooLarge)
+                ),
+                "{:?}",
+                options
+            );
+        }
+    }
+}